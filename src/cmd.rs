@@ -1,14 +1,52 @@
 use crate::config::Config;
 use duct::cmd;
-use std::{collections::HashMap, path::Path};
+use std::{
+    collections::HashMap,
+    path::Path,
+    str::FromStr,
+    time::{Duration, Instant},
+};
 
 #[derive(Debug, thiserror::Error)]
 pub enum Error {
     #[error("Could not find environment variable: {:?} in config files", key)]
     EnvVarMissing { key: String },
 
+    #[error("Expressions are disabled, could not evaluate: {:?}", expression)]
+    ExpressionsDisabled { expression: String },
+
+    #[error("Command {:?} is not allowed in expression: {:?}", command, expression)]
+    CommandNotAllowed { command: String, expression: String },
+
+    #[error("Expression timed out after {:?}: {:?}", timeout, expression)]
+    Timeout {
+        timeout: Duration,
+        expression: String,
+    },
+
+    #[error("Expression {:?} failed with {}", expression, status)]
+    ExpressionFailed {
+        expression: String,
+        status: std::process::ExitStatus,
+    },
+
+    #[error("Invalid expression settings: {}", msg)]
+    InvalidSettings { msg: String },
+
+    #[error("Command failed with {}: {}", status, stderr)]
+    CommandFailed {
+        status: std::process::ExitStatus,
+        stderr: String,
+    },
+
+    #[error("Command did not print anything")]
+    NoOutput,
+
     #[error(transparent)]
     IO(#[from] std::io::Error),
+
+    #[error(transparent)]
+    Config(#[from] crate::config::Error),
 }
 
 pub fn run(cmd: &str, config: &Config) -> Result<(), Error> {
@@ -78,10 +116,288 @@ pub fn read_with_dir_and_env(
         .map_err(Error::IO)
 }
 
+/// Runs `cmd` and returns its stdout. Unlike `read`, the output is captured even with
+/// `cmd.silent`, as it is data to be parsed and not shown. A command that fails or does not
+/// print anything is an error.
+pub fn capture(cmd: &str, config: &Config) -> Result<String, Error> {
+    let env = get_envs_with_config_envs(config)?;
+    captured_stdout(unsilenced_expression(cmd, &env), None)
+}
+
+/// Like `capture`, but runs the program `args[0]` directly, without a shell, so that
+/// arguments taken from the config can not inject commands. `input` is written to its stdin.
+/// The error of a failed command only contains its stderr, so data passed as input stays hidden.
+pub fn capture_args(
+    args: &[&str],
+    env: &HashMap<String, String>,
+    input: Option<&[u8]>,
+) -> Result<String, Error> {
+    let (program, args) = args.split_first().expect("at least the program");
+    captured_stdout(cmd(*program, args).full_env(env), input)
+}
+
+fn captured_stdout(exp: duct::Expression, input: Option<&[u8]>) -> Result<String, Error> {
+    let mut exp = exp.stdout_capture().stderr_capture().unchecked();
+    if let Some(input) = input {
+        exp = exp.stdin_bytes(input.to_vec());
+    }
+    let output = exp.run()?;
+
+    if !output.status.success() {
+        return Err(Error::CommandFailed {
+            status: output.status,
+            stderr: String::from_utf8_lossy(&output.stderr).trim().to_owned(),
+        });
+    }
+
+    let stdout = String::from_utf8_lossy(&output.stdout)
+        .trim_end_matches(['\n', '\r'])
+        .to_owned();
+    match stdout.trim().is_empty() {
+        true => Err(Error::NoOutput),
+        false => Ok(stdout),
+    }
+}
+
+/// Runs `cmd` and captures its stdout and stderr, even with `cmd.silent`. Unlike `read`, a
+/// non-zero exit status is not an error, so callers can inspect the output of commands that
+/// are expected to fail.
+pub fn read_output(cmd: &str, config: &Config) -> Result<std::process::Output, Error> {
+    let env = get_envs_with_config_envs(config)?;
+    unsilenced_expression(cmd, &env)
+        .stdout_capture()
+        .stderr_capture()
+        .unchecked()
+        .run()
+        .map_err(Error::IO)
+}
+
+/// Program that config expressions are evaluated with
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Interpreter {
+    #[default]
+    Bash,
+    Sh,
+    Python3,
+    /// Refuses to evaluate any expression, i.e. for untrusted repositories
+    Disabled,
+}
+
+impl FromStr for Interpreter {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "bash" => Ok(Interpreter::Bash),
+            "sh" => Ok(Interpreter::Sh),
+            "python3" => Ok(Interpreter::Python3),
+            "none" => Ok(Interpreter::Disabled),
+            s => Err(Error::InvalidSettings {
+                msg: format!(
+                    "unknown interpreter {:?}, expected one of \"bash\", \"sh\", \"python3\" or \"none\"",
+                    s
+                ),
+            }),
+        }
+    }
+}
+
+impl Interpreter {
+    fn program(&self) -> Option<&'static str> {
+        match self {
+            Interpreter::Bash => Some("bash"),
+            Interpreter::Sh => Some("sh"),
+            Interpreter::Python3 => Some("python3"),
+            Interpreter::Disabled => None,
+        }
+    }
+
+    fn is_shell(&self) -> bool {
+        matches!(self, Interpreter::Bash | Interpreter::Sh)
+    }
+}
+
+/// Restrictions that apply when evaluating a config expression
+#[derive(Debug, Clone, Default)]
+pub struct Sandbox {
+    pub interpreter: Interpreter,
+
+    /// The expression is killed if it runs longer than this
+    pub timeout: Option<Duration>,
+
+    /// Only these environment variables are passed to the expression
+    pub env: Option<Vec<String>>,
+
+    /// Only these commands may be called by the expression
+    pub allowed_commands: Option<Vec<String>>,
+}
+
+/// Evaluates a config expression with the restrictions of the given `sandbox` and returns
+/// its output, without trailing newlines
+pub fn read_expression(
+    expression: &str,
+    workdir: impl AsRef<Path>,
+    env: &HashMap<String, String>,
+    sandbox: &Sandbox,
+    config: &Config,
+) -> Result<String, Error> {
+    let program = sandbox
+        .interpreter
+        .program()
+        .ok_or_else(|| Error::ExpressionsDisabled {
+            expression: expression.to_owned(),
+        })?;
+
+    if let Some(allowed) = &sandbox.allowed_commands {
+        let commands = match sandbox.interpreter.is_shell() {
+            true => shell_commands(expression),
+            false => vec![program.to_owned()],
+        };
+        // commands that are only known when the script runs can not be checked at all
+        if let Some(command) = commands
+            .into_iter()
+            .find(|c| is_dynamic_command(c) || !allowed.iter().any(|a| a == c))
+        {
+            return Err(Error::CommandNotAllowed {
+                command: command.to_owned(),
+                expression: expression.to_owned(),
+            });
+        }
+    }
+
+    let env = match &sandbox.env {
+        Some(allowed) => env
+            .iter()
+            .filter(|(k, _)| allowed.contains(k))
+            .map(|(k, v)| (k.to_owned(), v.to_owned()))
+            .collect(),
+        None => env.to_owned(),
+    };
+
+    let mut exp = cmd!(program, "-c", expression)
+        .full_env(env)
+        .dir(workdir.as_ref())
+        .stdout_capture()
+        .unchecked();
+
+    if let Some(true) = config.get_bool("cmd.silent") {
+        exp = exp.stderr_null()
+    }
+
+    let handle = exp.start()?;
+    let output = match sandbox.timeout {
+        None => handle.wait()?,
+        Some(timeout) => {
+            let deadline = Instant::now() + timeout;
+            loop {
+                if let Some(output) = handle.try_wait()? {
+                    break output;
+                }
+                if Instant::now() >= deadline {
+                    handle.kill()?;
+                    return Err(Error::Timeout {
+                        timeout,
+                        expression: expression.to_owned(),
+                    });
+                }
+                std::thread::sleep(Duration::from_millis(10));
+            }
+        }
+    };
+
+    if !output.status.success() {
+        return Err(Error::ExpressionFailed {
+            expression: expression.to_owned(),
+            status: output.status,
+        });
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .trim_end_matches(['\n', '\r'])
+        .to_owned())
+}
+
+/// Best effort extraction of the commands a shell script calls, which is the first word of
+/// every pipeline, list, subshell or command substitution that is not a variable assignment,
+/// a redirection or a shell keyword
+fn shell_commands(script: &str) -> Vec<String> {
+    const KEYWORDS: &[&str] = &[
+        "if", "then", "else", "elif", "fi", "for", "while", "until", "do", "done", "case", "esac",
+        "in", "!", "{", "}", "[[", "]]",
+    ];
+
+    let mut segments = vec![String::new()];
+    let mut quote = None;
+
+    for c in script.chars() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some('"'), '(' | ')' | '`') | (None, '(' | ')' | '`' | '|' | ';' | '&' | '\n') => {
+                segments.push(String::new());
+                continue;
+            }
+            (None, '\'' | '"') => quote = Some(c),
+            _ => {}
+        }
+        segments.last_mut().expect("at least one segment").push(c);
+    }
+
+    segments
+        .iter()
+        .filter_map(|segment| {
+            let mut words = segment.split_whitespace();
+            while let Some(word) = words.next() {
+                match redirection(word) {
+                    // the target of the redirection is the next word
+                    Some(true) => {
+                        words.next();
+                    }
+                    Some(false) => {}
+                    None if KEYWORDS.contains(&word) || is_assignment(word) => {}
+                    None => return Some(word),
+                }
+            }
+            None
+        })
+        .map(|word| word.trim_matches(['\'', '"']).to_owned())
+        .filter(|word| !word.is_empty())
+        .collect()
+}
+
+/// Whether `word` is a redirection like `>out`, `2>` or `<<`, and if so, whether its target
+/// is the next word
+fn redirection(word: &str) -> Option<bool> {
+    let operator = word.trim_start_matches(|c: char| c.is_ascii_digit());
+    if !operator.starts_with(['<', '>']) {
+        return None;
+    }
+
+    Some(
+        operator
+            .chars()
+            .all(|c| matches!(c, '<' | '>' | '&' | '|' | '-')),
+    )
+}
+
+/// Commands that are computed when the script runs, like `$cmd`, or that run code of their
+/// own, like `eval`
+fn is_dynamic_command(command: &str) -> bool {
+    command.contains(['$', '`', '\\']) || matches!(command, "eval" | "exec" | "source" | ".")
+}
+
+fn is_assignment(word: &str) -> bool {
+    match word.split_once('=') {
+        Some((name, _)) => {
+            !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+        }
+        None => false,
+    }
+}
+
 /// Sets up a duct expression from the given `cmd` parameter, sets its environment from
 /// the given `env` parameter and configures it with settings found in the given `config` parameter
 fn expression(cmd: &str, env: &HashMap<String, String>, config: &Config) -> duct::Expression {
-    let mut exp = cmd!("bash", "-c", cmd).full_env(env);
+    let mut exp = unsilenced_expression(cmd, env);
 
     if let Some(b) = config.get_bool("cmd.silent") {
         if *b {
@@ -92,8 +408,14 @@ fn expression(cmd: &str, env: &HashMap<String, String>, config: &Config) -> duct
     exp
 }
 
-fn get_envs_with_config_envs(config: &Config) -> Result<HashMap<String, String>, Error> {
-    let mut config_envs = config.get_envs();
+/// Like `expression`, for commands whose output is captured and must not be discarded by
+/// `cmd.silent`
+fn unsilenced_expression(cmd: &str, env: &HashMap<String, String>) -> duct::Expression {
+    cmd!("bash", "-c", cmd).full_env(env)
+}
+
+pub(crate) fn get_envs_with_config_envs(config: &Config) -> Result<HashMap<String, String>, Error> {
+    let mut config_envs = config.try_get_envs()?;
 
     ensure_env_var(&config_envs, "AWS_PROFILE")?;
     ensure_env_var(&config_envs, "AWS_DEFAULT_REGION")?;
@@ -127,3 +449,35 @@ fn ensure_env_var_or_default(
         })
         .unwrap()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_shell_commands() {
+        assert_eq!(shell_commands("echo test"), vec!["echo"]);
+        assert_eq!(
+            shell_commands("A=1 aws s3 ls | grep \"a|b\" && echo \"$(date)\"; `whoami`"),
+            vec!["aws", "grep", "echo", "date", "whoami"]
+        );
+        assert_eq!(
+            shell_commands("if test -f x; then cat x; fi"),
+            vec!["test", "cat"]
+        );
+        assert_eq!(shell_commands("echo 'a;b' > out"), vec!["echo"]);
+        assert_eq!(shell_commands(">/dev/null rm -rf x"), vec!["rm"]);
+        assert_eq!(shell_commands("2> err < in rm -rf x"), vec!["rm"]);
+        assert_eq!(shell_commands("a=rm; $a -rf x"), vec!["$a"]);
+        assert_eq!(shell_commands("$(echo rm) -rf x"), vec!["$", "echo", "-rf"]);
+    }
+
+    #[test]
+    fn test_dynamic_commands() {
+        assert!(is_dynamic_command("$a"));
+        assert!(is_dynamic_command("${a}"));
+        assert!(is_dynamic_command("r\\m"));
+        assert!(is_dynamic_command("eval"));
+        assert!(!is_dynamic_command("echo"));
+    }
+}
@@ -0,0 +1,166 @@
+use super::{Change, Config, Options};
+use anyhow::Result;
+use std::path::{Path, PathBuf};
+use toml::Value;
+
+pub fn get(key: impl AsRef<str>, show_source: bool, config: &Config) -> Result<()> {
+    let (value, filepath) = config
+        .get_with_filepath(&key)
+        .ok_or_else(|| anyhow::anyhow!("Could not find key {:?} in config", key.as_ref()))?;
+
+    if show_source {
+        println!(
+            "{}\t{}",
+            format_value(value),
+            format_source(&key, filepath, config)
+        );
+    } else {
+        println!("{}", format_value(value));
+    }
+
+    Ok(())
+}
+
+pub fn list(config: &Config) -> Result<()> {
+    for key in config.keys() {
+        if let Some((value, filepath)) = config.get_with_filepath(&key) {
+            println!(
+                "{}\t{}\t{}",
+                key,
+                format_value(value),
+                format_source(&key, filepath, config)
+            );
+        }
+    }
+
+    Ok(())
+}
+
+pub fn explain(key: impl AsRef<str>, config: &Config) -> Result<()> {
+    let layers = config.get_all_with_filepath(&key);
+
+    if layers.is_empty() {
+        anyhow::bail!("Could not find key {:?} in config", key.as_ref());
+    }
+
+    let contributing = config.contributing_layers(&key);
+
+    println!("{}", key.as_ref());
+    for (i, (value, filepath)) in layers.into_iter().enumerate() {
+        let marker = if i < contributing { "*" } else { " " };
+        println!(
+            "{} {}\t{}",
+            marker,
+            format_source(&key, filepath, config),
+            format_value(value)
+        );
+    }
+
+    Ok(())
+}
+
+pub fn set(
+    key: impl AsRef<str>,
+    value: impl AsRef<str>,
+    file: impl AsRef<Path>,
+    config: &mut Config,
+) -> Result<()> {
+    config.set_in_file(key, Config::parse_value(value), file)?;
+
+    Ok(())
+}
+
+pub fn diff(
+    a: impl AsRef<Path>,
+    b: impl AsRef<Path>,
+    resolve: bool,
+    decrypt: bool,
+    options: Options,
+) -> Result<()> {
+    let a = Config::from_paths([a], options.clone())?;
+    let b = Config::from_paths([b], options)?;
+
+    for change in a.diff(&b, resolve, decrypt)? {
+        match change {
+            Change::Added { key, value } => println!("+ {}\t{}", key, format_value(&value)),
+            Change::Removed { key, value } => println!("- {}\t{}", key, format_value(&value)),
+            Change::Changed { key, from, to } => println!(
+                "~ {}\t{} -> {}",
+                key,
+                format_value(&from),
+                format_value(&to)
+            ),
+        }
+    }
+
+    Ok(())
+}
+
+pub fn lock(config: &mut Config) -> Result<()> {
+    let lock_path = config.lock()?;
+    println!("{}", lock_path.to_string_lossy());
+
+    Ok(())
+}
+
+pub fn schema(config: &Config) -> Result<()> {
+    println!("{}", serde_json::to_string_pretty(&config.schema()?)?);
+
+    Ok(())
+}
+
+pub fn encrypt(
+    key: impl AsRef<str>,
+    value: Option<String>,
+    kms: Option<String>,
+    file: Option<PathBuf>,
+    config: &mut Config,
+) -> Result<()> {
+    let defined = config.get_with_filepath(&key);
+
+    let plaintext = match (value, &defined) {
+        (Some(value), _) => value,
+        (None, Some((Value::String(s), _))) => s.to_owned(),
+        (None, Some((v, _))) => anyhow::bail!(
+            "Can only encrypt strings, {:?} is {}",
+            key.as_ref(),
+            format_value(v)
+        ),
+        (None, None) => anyhow::bail!("Could not find key {:?} in config", key.as_ref()),
+    };
+
+    let file = match (file, defined) {
+        (Some(file), _) => file,
+        (None, Some((_, filepath))) if filepath != Config::override_path() => filepath,
+        (None, _) => anyhow::bail!(
+            "{:?} is not defined in a config file, pass the file to write to with --file",
+            key.as_ref()
+        ),
+    };
+
+    let encrypted = config.encrypt(&key, &plaintext, kms.as_deref())?;
+    config.set_in_file(key, encrypted, file)?;
+
+    Ok(())
+}
+
+/// Strings are printed without quotes, tables as TOML and everything else in its inline form
+fn format_value(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.to_owned(),
+        Value::Table(t) => toml::to_string(t)
+            .map(|s| s.trim_end().to_owned())
+            .unwrap_or_else(|_| value.to_string()),
+        v => v.to_string(),
+    }
+}
+
+/// Values in the overrides layer also show whether they came from `--set` or an env var
+fn format_source(key: impl AsRef<str>, filepath: PathBuf, config: &Config) -> String {
+    match config.override_source(key) {
+        Some(source) if filepath == Config::override_path() => {
+            format!("{} ({})", filepath.to_string_lossy(), source)
+        }
+        _ => filepath.to_string_lossy().to_string(),
+    }
+}
@@ -0,0 +1,135 @@
+use super::{as_expression, secrets::as_encrypted, Config, Error};
+use std::collections::{BTreeMap, HashMap};
+use toml::{value::Map, Value};
+
+/// A key that differs between the merged views of two configs
+#[derive(Debug, Clone, PartialEq)]
+pub enum Change {
+    Added { key: String, value: Value },
+    Removed { key: String, value: Value },
+    Changed { key: String, from: Value, to: Value },
+}
+
+/// Diff
+impl Config {
+    /// Compares the merged view of this config with the one of `other`, key by key. With
+    /// `resolve`, expressions are compared by what they resolve to. Encrypted values are
+    /// compared by their ciphertexts, unless `decrypt` is set as well.
+    pub fn diff(&self, other: &Config, resolve: bool, decrypt: bool) -> Result<Vec<Change>, Error> {
+        let mut before = self.flattened(resolve, decrypt)?;
+        let after = other.flattened(resolve, decrypt)?;
+
+        let mut changes = Vec::new();
+        for (key, to) in after {
+            match before.remove(&key) {
+                None => changes.push(Change::Added { key, value: to }),
+                Some(from) if from != to => changes.push(Change::Changed { key, from, to }),
+                Some(_) => {}
+            }
+        }
+        changes.extend(
+            before
+                .into_iter()
+                .map(|(key, value)| Change::Removed { key, value }),
+        );
+
+        changes.sort_by(|a, b| a.key().cmp(b.key()));
+
+        Ok(changes)
+    }
+
+    /// All values of the merged config that are not tables, by their dotted keys
+    fn flattened(&self, resolve: bool, decrypt: bool) -> Result<BTreeMap<String, Value>, Error> {
+        let envs = match (resolve, decrypt) {
+            (true, true) => self.try_get_envs()?,
+            (true, false) => self.get_envs_without_decrypting()?,
+            (false, _) => HashMap::new(),
+        };
+
+        let mut values = BTreeMap::new();
+        if let Value::Table(t) = self.merged() {
+            self.flatten("", t, resolve.then_some(&envs), decrypt, &mut values)?;
+        }
+
+        Ok(values)
+    }
+
+    fn flatten(
+        &self,
+        prefix: &str,
+        table: &Map<String, Value>,
+        envs: Option<&HashMap<String, String>>,
+        decrypt: bool,
+        values: &mut BTreeMap<String, Value>,
+    ) -> Result<(), Error> {
+        for (key, val) in table {
+            let key = match prefix.is_empty() {
+                true => key.to_owned(),
+                false => format!("{}.{}", prefix, key),
+            };
+
+            if let Some(envs) = envs {
+                if let Some(resolved) = self.resolve(&key, val, envs, decrypt)? {
+                    values.insert(key, Value::String(resolved));
+                    continue;
+                }
+            }
+
+            match val {
+                Value::Table(t) => self.flatten(&key, t, envs, decrypt, values)?,
+                v => {
+                    values.insert(key, v.to_owned());
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Returns what `val` resolves to if it is an env value, an expression or, with `decrypt`,
+    /// an encrypted value
+    fn resolve(
+        &self,
+        key: &str,
+        val: &Value,
+        envs: &HashMap<String, String>,
+        decrypt: bool,
+    ) -> Result<Option<String>, Error> {
+        // plaintexts would end up in the output, the ciphertexts are compared without `decrypt`
+        if as_encrypted(val).is_some() {
+            return match decrypt {
+                true => self.decrypt(key, val),
+                false => Ok(None),
+            };
+        }
+
+        // env values are already resolved, including the ones that depend on each other
+        if let Some(resolved) = key.strip_prefix("env.").and_then(|name| envs.get(name)) {
+            return Ok(Some(resolved.to_owned()));
+        }
+
+        match val.as_str().and_then(as_expression) {
+            Some(exp) => {
+                let (_, filepath) = self.get_with_filepath(key).expect("key exists");
+                let filepath = self.defining_file(key, filepath);
+                self.evaluate_expression(exp, filepath, envs)
+                    .map(Some)
+                    .map_err(|e| Error::Evaluate {
+                        key: key.to_owned(),
+                        msg: e.to_string(),
+                    })
+            }
+            None => Ok(None),
+        }
+    }
+}
+
+impl Change {
+    pub fn key(&self) -> &str {
+        match self {
+            Change::Added { key, .. }
+            | Change::Removed { key, .. }
+            | Change::Changed { key, .. } => key,
+        }
+    }
+}
@@ -0,0 +1,166 @@
+use super::{Config, Error};
+use std::path::Path;
+use toml::{value::Map, Value};
+
+const ENV_FILE_KEY: &str = "env_file";
+const ENV_KEY: &str = "env";
+
+/// Dotenv
+impl Config {
+    /// Loads the dotenv files listed in the `env_file` entry of `file` into its `[env]` table.
+    /// Paths are relative to the directory of `filepath`. Entries of the `[env]` table itself
+    /// take precedence over the dotenv files, and later dotenv files over earlier ones.
+    pub(crate) fn import_env_files(file: &mut Value, filepath: &Path) -> Result<(), Error> {
+        let env_files = match file.get(ENV_FILE_KEY) {
+            None => return Ok(()),
+            Some(Value::String(s)) => vec![s.to_owned()],
+            Some(Value::Array(a)) => a
+                .iter()
+                .map(|v| {
+                    v.as_str().map(ToOwned::to_owned).ok_or_else(|| {
+                        Error::load_error(
+                            filepath,
+                            &format!("'{}' entries should be strings, found {}", ENV_FILE_KEY, v),
+                        )
+                    })
+                })
+                .collect::<Result<_, _>>()?,
+            Some(v) => {
+                return Err(Error::load_error(
+                    filepath,
+                    &format!(
+                        "'{}' should be a path or a list of paths, found {}",
+                        ENV_FILE_KEY, v
+                    ),
+                ))
+            }
+        };
+
+        let dir = filepath.parent().expect("has parent");
+        let mut envs = Map::new();
+
+        for env_file in env_files {
+            let path = dir.join(&env_file);
+            let contents = std::fs::read_to_string(&path).map_err(|e| {
+                Error::load_error(filepath, &format!("could not read {:?}: {}", env_file, e))
+            })?;
+            let vars = parse_dotenv(&contents)
+                .map_err(|msg| Error::load_error(&path, &format!("Invalid dotenv: {}", msg)))?;
+
+            envs.extend(vars.into_iter().map(|(k, v)| (k, Value::String(v))));
+        }
+
+        let table = file.as_table_mut().expect("table at root");
+        match table.get_mut(ENV_KEY) {
+            Some(Value::Table(existing)) => {
+                envs.extend(std::mem::take(existing));
+                *existing = envs;
+            }
+            Some(v) => {
+                return Err(Error::load_error(
+                    filepath,
+                    &format!("'{}' should be a table, found {}", ENV_KEY, v),
+                ))
+            }
+            None => {
+                table.insert(ENV_KEY.to_owned(), Value::Table(envs));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Parses `KEY=VALUE` lines, with optional `export` prefixes, `#` comments and single or
+/// double quoted values. Escapes are only interpreted inside double quotes.
+pub(crate) fn parse_dotenv(contents: &str) -> Result<Vec<(String, String)>, String> {
+    let mut vars = Vec::new();
+
+    for (i, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let line = line.strip_prefix("export ").unwrap_or(line);
+        let (key, raw) = line
+            .split_once('=')
+            .ok_or_else(|| format!("line {}: expected KEY=VALUE", i + 1))?;
+        let key = key.trim();
+        if key.is_empty() || !key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+            return Err(format!("line {}: invalid key {:?}", i + 1, key));
+        }
+
+        let raw = raw.trim();
+        let value = if let Some(rest) = raw.strip_prefix('"') {
+            unescape(rest).ok_or_else(|| format!("line {}: unterminated quote", i + 1))?
+        } else if let Some(rest) = raw.strip_prefix('\'') {
+            rest.split_once('\'')
+                .map(|(v, _)| v.to_owned())
+                .ok_or_else(|| format!("line {}: unterminated quote", i + 1))?
+        } else {
+            match raw.find(" #") {
+                Some(end) => raw[..end].trim_end().to_owned(),
+                None => raw.to_owned(),
+            }
+        };
+
+        vars.push((key.to_owned(), value));
+    }
+
+    Ok(vars)
+}
+
+/// Reads a double quoted value up to its closing quote
+fn unescape(s: &str) -> Option<String> {
+    let mut value = String::new();
+    let mut chars = s.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => return Some(value),
+            '\\' => match chars.next()? {
+                'n' => value.push('\n'),
+                't' => value.push('\t'),
+                'r' => value.push('\r'),
+                c => value.push(c),
+            },
+            c => value.push(c),
+        }
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_dotenv() {
+        let contents = r#"
+# comment
+export A=1
+B = two words # trailing comment
+C="quoted \"value\"\nnext line"
+D='single # not a comment'
+E=
+"#;
+        let vars = parse_dotenv(contents).unwrap();
+
+        assert_eq!(
+            vars,
+            vec![
+                ("A".to_string(), "1".to_string()),
+                ("B".to_string(), "two words".to_string()),
+                ("C".to_string(), "quoted \"value\"\nnext line".to_string()),
+                ("D".to_string(), "single # not a comment".to_string()),
+                ("E".to_string(), "".to_string()),
+            ]
+        );
+
+        assert!(parse_dotenv("INVALID").is_err());
+        assert!(parse_dotenv("A=\"unterminated").is_err());
+        assert!(parse_dotenv("IN VALID=1").is_err());
+    }
+}
@@ -0,0 +1,131 @@
+use super::{merge::merge_value, Config, Error};
+use std::path::PathBuf;
+use toml::{value::Map, Value};
+
+const ENVIRONMENTS_KEY: &str = "environments";
+
+/// Environments
+impl Config {
+    /// Applies the `[environments.<name>]` table as a layer on top of all config files.
+    /// Values from an environment it `extends` are used unless they are redefined.
+    pub(crate) fn apply_environment(&mut self, name: impl AsRef<str>) -> Result<(), Error> {
+        let mut chain = Vec::new();
+        let overlay = self.resolve_environment(name.as_ref(), &mut chain)?;
+        let filepath = Self::environment_path(&name);
+
+        // each value comes from the first environment of the chain that defines it
+        for key in leaf_keys("", &overlay) {
+            let source = chain.iter().find_map(|name| {
+                self.get_with_filepath(format!("{}.{}.{}", ENVIRONMENTS_KEY, name, key))
+                    .map(|(_, source)| source)
+            });
+            if let Some(source) = source {
+                self.environment_sources
+                    .insert((filepath.clone(), key), source);
+            }
+        }
+
+        self.file_map
+            .insert(filepath.clone(), Value::Table(overlay));
+        self.layers.insert(0, filepath);
+        self.environment = Some(name.as_ref().to_owned());
+        self.invalidate_cache();
+
+        Ok(())
+    }
+
+    /// The pseudo path that is reported as the source of values coming from an environment
+    pub fn environment_path(name: impl AsRef<str>) -> PathBuf {
+        PathBuf::from(format!("{}.{}", ENVIRONMENTS_KEY, name.as_ref()))
+    }
+
+    /// The config file that defines `key` if `filepath` is the pseudo path of an environment,
+    /// otherwise `filepath` itself. Expressions of an environment are evaluated in the
+    /// directory of the file that defines them.
+    pub(crate) fn defining_file(&self, key: impl AsRef<str>, filepath: PathBuf) -> PathBuf {
+        self.environment_sources
+            .get(&(filepath.clone(), key.as_ref().to_owned()))
+            .cloned()
+            .unwrap_or(filepath)
+    }
+
+    fn resolve_environment(
+        &self,
+        name: &str,
+        chain: &mut Vec<String>,
+    ) -> Result<Map<String, Value>, Error> {
+        if chain.iter().any(|n| n == name) {
+            chain.push(name.to_owned());
+            return Err(Error::InvalidEnvironment {
+                name: chain[0].to_owned(),
+                msg: format!("cyclic 'extends': {}", chain.join(" -> ")),
+            });
+        }
+        chain.push(name.to_owned());
+
+        let key = format!("{}.{}", ENVIRONMENTS_KEY, name);
+        match self.get(&key) {
+            Some(Value::Table(_)) => {}
+            Some(_) => {
+                return Err(Error::InvalidEnvironment {
+                    name: name.to_owned(),
+                    msg: format!("{:?} is not a table", key),
+                })
+            }
+            None => {
+                return Err(Error::UnknownEnvironment {
+                    name: name.to_owned(),
+                })
+            }
+        }
+
+        let mut table = self.get_merged_tables(&key);
+
+        let overlay = match table.remove("extends") {
+            Some((Value::String(base), _)) => self.resolve_environment(&base, chain)?,
+            Some((v, _)) => {
+                return Err(Error::InvalidEnvironment {
+                    name: name.to_owned(),
+                    msg: format!(
+                        "'extends' should be the name of an environment, found {}",
+                        v
+                    ),
+                })
+            }
+            None => Map::new(),
+        };
+
+        let strategies = self.array_strategies()?;
+        let mut overlay = Value::Table(overlay);
+        for (key, (val, _)) in table {
+            merge_value(
+                &mut overlay,
+                Value::Table(Map::from_iter([(key, val)])),
+                "",
+                &strategies,
+            );
+        }
+
+        match overlay {
+            Value::Table(overlay) => Ok(overlay),
+            _ => unreachable!("merging tables results in a table"),
+        }
+    }
+}
+
+/// The dotted keys of all values in `table` that are not tables
+fn leaf_keys(prefix: &str, table: &Map<String, Value>) -> Vec<String> {
+    table
+        .iter()
+        .flat_map(|(key, val)| {
+            let key = match prefix.is_empty() {
+                true => key.to_owned(),
+                false => format!("{}.{}", prefix, key),
+            };
+            match val {
+                Value::Table(t) => leaf_keys(&key, t),
+                _ => vec![key],
+            }
+        })
+        .collect()
+}
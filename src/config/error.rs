@@ -4,6 +4,40 @@ use std::path::Path;
 pub enum Error {
     #[error("error while loading the config file at {:?}\n\t{:?}", path, msg)]
     Load { path: String, msg: String },
+
+    #[error("error while writing the config file at {:?}\n\t{:?}", path, msg)]
+    Write { path: String, msg: String },
+
+    #[error("could not find key {:?} in config", key)]
+    MissingKey { key: String },
+
+    #[error("invalid value for {:?} in {:?}\n\t{}", key, path, msg)]
+    Deserialize {
+        key: String,
+        path: String,
+        msg: String,
+    },
+
+    #[error("invalid override {:?}, expected the form key=value", arg)]
+    InvalidOverride { arg: String },
+
+    #[error("environment {:?} is not defined in any config file", name)]
+    UnknownEnvironment { name: String },
+
+    #[error("invalid environment {:?}\n\t{}", name, msg)]
+    InvalidEnvironment { name: String, msg: String },
+
+    #[error("could not decrypt the value of {:?}\n\t{}", key, msg)]
+    Decrypt { key: String, msg: String },
+
+    #[error("could not encrypt the value of {:?}\n\t{}", key, msg)]
+    Encrypt { key: String, msg: String },
+
+    #[error("could not evaluate the expression of {:?}\n\t{}", key, msg)]
+    Evaluate { key: String, msg: String },
+
+    #[error("invalid schema declaration for {:?}\n\t{}", key, msg)]
+    InvalidSchema { key: String, msg: String },
 }
 
 #[cfg(not(tarpaulin_include))]
@@ -18,4 +52,15 @@ impl Error {
             msg: msg.to_string(),
         }
     }
+
+    pub fn write_error(path: impl AsRef<Path>, msg: &str) -> Error {
+        Error::Write {
+            path: path
+                .as_ref()
+                .to_str()
+                .expect("invalid utf-8 in path")
+                .to_string(),
+            msg: msg.to_string(),
+        }
+    }
 }
@@ -0,0 +1,273 @@
+use super::{Config, Error, OVERRIDE_FILEPATH};
+use crate::cmd::{self, read_expression, Interpreter, Sandbox};
+use sha2::{Digest, Sha256};
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+use toml::Value;
+
+const EXPRESSIONS_KEY: &str = "expressions";
+const INTERPRETER_PREFIX: &str = "#!";
+
+/// Returns the trimmed shell expression if `value` is wrapped in `{{ }}`
+pub(crate) fn as_expression(value: &str) -> Option<&str> {
+    if value.starts_with("{{") && value.ends_with("}}") && value.len() >= 4 {
+        Some(value[2..value.len() - 2].trim())
+    } else {
+        None
+    }
+}
+
+/// Collects the names of all variables referenced as `$NAME` or `${NAME}` in an expression
+pub(crate) fn referenced_variables(expression: &str) -> Vec<&str> {
+    let is_name_char = |c: char| c.is_ascii_alphanumeric() || c == '_';
+
+    expression
+        .match_indices('$')
+        .filter_map(|(i, _)| {
+            let rest = &expression[i + 1..];
+            let rest = rest.strip_prefix('{').unwrap_or(rest);
+            let end = rest.find(|c| !is_name_char(c)).unwrap_or(rest.len());
+            (end > 0).then(|| &rest[..end])
+        })
+        .collect()
+}
+
+/// Hashes the applied environment and the values of the variables that `expression` references,
+/// so that its cached or locked result is not used with other values. Only the hash is kept, as
+/// the values can be secrets.
+fn inputs_hash(
+    expression: &str,
+    environment: Option<&str>,
+    env: &HashMap<String, String>,
+) -> String {
+    let mut names = referenced_variables(expression);
+    names.sort();
+    names.dedup();
+
+    let mut hasher = Sha256::new();
+    if let Some(environment) = environment {
+        hasher.update(environment);
+        hasher.update([0]);
+    }
+    for name in names {
+        if let Some(value) = env.get(name) {
+            hasher.update(name);
+            hasher.update([0]);
+            hasher.update(value);
+            hasher.update([0]);
+        }
+    }
+
+    hasher.finalize()[..8]
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+/// Expressions
+impl Config {
+    /// Drops all merged and resolved values, so they get computed again after the config was modified
+    pub(crate) fn invalidate_cache(&mut self) {
+        self.merged.take();
+        self.envs.take();
+        self.expressions.get_mut().clear();
+    }
+
+    /// Evaluates an expression that is defined in the layer at `filepath`, in the directory of
+    /// that layer and with the `[expressions]` settings that apply to it. A leading `#!<name>`
+    /// picks the interpreter for this expression only, i.e. `{{ #!python3 print(1) }}`.
+    pub(crate) fn evaluate_expression(
+        &self,
+        expression: &str,
+        filepath: impl AsRef<Path>,
+        env: &HashMap<String, String>,
+    ) -> Result<String, cmd::Error> {
+        // pseudo and remote layers like the overrides are evaluated in the current directory
+        let workdir = match filepath.as_ref().parent() {
+            Some(dir) if dir.is_dir() => dir,
+            _ => Path::new("."),
+        };
+
+        self.cached_expression(expression, workdir, env, || {
+            let mut sandbox = self.sandbox(&filepath)?;
+
+            let code = match expression.strip_prefix(INTERPRETER_PREFIX) {
+                Some(rest) => {
+                    let (name, code) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+                    // a value can not enable expressions once they are disabled
+                    if sandbox.interpreter != Interpreter::Disabled {
+                        sandbox.interpreter = name.parse()?;
+                    }
+                    code.trim()
+                }
+                None => expression,
+            };
+
+            read_expression(code, workdir, env, &sandbox, self)
+        })
+    }
+
+    /// The `[expressions]` settings for values defined in the layer at `filepath`. Settings in
+    /// the overrides take precedence over the layer itself, which takes precedence over all
+    /// other layers. This way `--set expressions.interpreter=none` disables all expressions.
+    pub(crate) fn sandbox(&self, filepath: impl AsRef<Path>) -> Result<Sandbox, cmd::Error> {
+        let setting = |name: &str| {
+            let key = format!("{}.{}", EXPRESSIONS_KEY, name);
+            self.get_from_file(&key, OVERRIDE_FILEPATH)
+                .or_else(|| self.get_from_file(&key, &filepath))
+                .or_else(|| self.get(&key))
+                .map(|v| (key, v))
+        };
+        let invalid = |key: &str, expected: &str, v: &Value| cmd::Error::InvalidSettings {
+            msg: format!("{:?} should be {}, found {}", key, expected, v),
+        };
+        let strings = |name: &str| match setting(name) {
+            None => Ok(None),
+            Some((key, v)) => v
+                .as_array()
+                .and_then(|a| {
+                    a.iter()
+                        .map(|v| v.as_str().map(ToOwned::to_owned))
+                        .collect::<Option<Vec<_>>>()
+                })
+                .map(Some)
+                .ok_or_else(|| invalid(&key, "a list of strings", v)),
+        };
+
+        let interpreter = match setting("interpreter") {
+            None => Interpreter::default(),
+            Some((_, Value::String(s))) => s.parse()?,
+            Some((key, v)) => return Err(invalid(&key, "a string", v)),
+        };
+
+        let timeout = match setting("timeout") {
+            None => None,
+            Some((_, Value::Integer(i))) if *i > 0 => Some(Duration::from_secs(*i as u64)),
+            Some((_, Value::Float(f))) if *f > 0.0 => Some(Duration::from_secs_f64(*f)),
+            Some((key, v)) => return Err(invalid(&key, "a positive number of seconds", v)),
+        };
+
+        Ok(Sandbox {
+            interpreter,
+            timeout,
+            env: strings("env")?,
+            allowed_commands: strings("allowed_commands")?,
+        })
+    }
+
+    /// Evaluates `expression` in `workdir` with the given `eval` function, unless the same
+    /// expression has already been evaluated in the same directory and with the same values of
+    /// the variables it references during this run, or its result is pinned in the lock file for
+    /// the same inputs.
+    pub(crate) fn cached_expression<E>(
+        &self,
+        expression: &str,
+        workdir: impl AsRef<Path>,
+        env: &HashMap<String, String>,
+        eval: impl FnOnce() -> Result<String, E>,
+    ) -> Result<String, E> {
+        let cache_key = (
+            workdir.as_ref().to_owned(),
+            expression.to_owned(),
+            inputs_hash(expression, self.environment.as_deref(), env),
+        );
+
+        if let Some(val) = self.locked.get(&cache_key) {
+            return Ok(val.to_owned());
+        }
+
+        if let Some(val) = self.expressions.borrow().get(&cache_key) {
+            return Ok(val.to_owned());
+        }
+
+        let val = eval()?;
+        self.expressions.borrow_mut().insert(cache_key, val.clone());
+
+        Ok(val)
+    }
+
+    /// Resolves all `{{ }}` expressions found in `envs`. Expressions referencing other env
+    /// keys that are themselves expressions are evaluated after their dependencies.
+    pub(crate) fn resolve_expression_values(
+        &self,
+        envs: &HashMap<String, (String, PathBuf)>,
+    ) -> Result<HashMap<String, String>, Error> {
+        let mut resolved = HashMap::new();
+
+        for key in envs.keys() {
+            self.resolve_expression_value(key, envs, &mut resolved, &mut Vec::new())?;
+        }
+
+        Ok(resolved)
+    }
+
+    fn resolve_expression_value(
+        &self,
+        key: &str,
+        envs: &HashMap<String, (String, PathBuf)>,
+        resolved: &mut HashMap<String, String>,
+        visiting: &mut Vec<String>,
+    ) -> Result<(), Error> {
+        if resolved.contains_key(key) {
+            return Ok(());
+        }
+
+        let (val, config_path) = &envs[key];
+
+        let exp = match as_expression(val) {
+            Some(exp) => exp,
+            None => {
+                resolved.insert(key.to_owned(), val.to_owned());
+                return Ok(());
+            }
+        };
+
+        if visiting.iter().any(|k| k == key) {
+            visiting.push(key.to_owned());
+            return Err(Error::Evaluate {
+                key: key.to_owned(),
+                msg: format!(
+                    "cyclic dependency between expressions: {}",
+                    visiting.join(" -> ")
+                ),
+            });
+        }
+
+        visiting.push(key.to_owned());
+        for dependency in referenced_variables(exp) {
+            if dependency != key && envs.contains_key(dependency) {
+                self.resolve_expression_value(dependency, envs, resolved, visiting)?;
+            }
+        }
+        visiting.pop();
+
+        let current_envs = envs
+            .iter()
+            .map(|(k, (v, _))| (k.to_owned(), resolved.get(k).unwrap_or(v).to_owned()))
+            .collect::<HashMap<_, _>>();
+        let val = self
+            .evaluate_expression(exp, config_path, &current_envs)
+            .map_err(|e| Error::Evaluate {
+                key: key.to_owned(),
+                msg: e.to_string(),
+            })?;
+
+        resolved.insert(key.to_owned(), val);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_referenced_variables() {
+        assert_eq!(referenced_variables("echo test"), Vec::<&str>::new());
+        assert_eq!(referenced_variables("echo $A-${B_2} $"), vec!["A", "B_2"]);
+        assert_eq!(referenced_variables("echo \"$A\"/${B}c"), vec!["A", "B"]);
+    }
+}
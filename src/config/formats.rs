@@ -0,0 +1,115 @@
+use super::Error;
+use std::path::Path;
+use toml::{value::Map, Value};
+use yaml_rust2::{Yaml, YamlLoader};
+
+/// File formats a config layer can be written in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Format {
+    Toml,
+    Yaml,
+    Json,
+}
+
+impl Format {
+    /// Picks the format from the file extension, anything unknown is treated as TOML
+    pub(crate) fn from_path(path: impl AsRef<Path>) -> Format {
+        match path.as_ref().extension().and_then(|e| e.to_str()) {
+            Some("yaml") | Some("yml") => Format::Yaml,
+            Some("json") => Format::Json,
+            _ => Format::Toml,
+        }
+    }
+
+    /// Parses `contents` into the same `Value` tree, no matter the format
+    pub(crate) fn parse(&self, contents: &str, path: impl AsRef<Path>) -> Result<Value, Error> {
+        match self {
+            Format::Toml => contents
+                .parse::<Value>()
+                .map_err(|e| Error::load_error(path, &format!("Invalid TOML: {}", e))),
+            Format::Yaml => {
+                let mut docs = YamlLoader::load_from_str(contents)
+                    .map_err(|e| Error::load_error(&path, &format!("Invalid YAML: {}", e)))?;
+                match docs.len() {
+                    0 => Ok(Value::Table(Map::new())),
+                    1 => match yaml_to_value(docs.remove(0)) {
+                        Ok(Some(v @ Value::Table(_))) => Ok(v),
+                        Ok(_) => Err(Error::load_error(path, "expected a mapping at the root")),
+                        Err(msg) => Err(Error::load_error(path, &format!("Invalid YAML: {}", msg))),
+                    },
+                    _ => Err(Error::load_error(path, "expected a single YAML document")),
+                }
+            }
+            Format::Json => {
+                let json = serde_json::from_str::<serde_json::Value>(contents)
+                    .map_err(|e| Error::load_error(&path, &format!("Invalid JSON: {}", e)))?;
+                match json_to_value(json) {
+                    Ok(Some(v @ Value::Table(_))) => Ok(v),
+                    Ok(_) => Err(Error::load_error(path, "expected an object at the root")),
+                    Err(msg) => Err(Error::load_error(path, &format!("Invalid JSON: {}", msg))),
+                }
+            }
+        }
+    }
+}
+
+/// Converts a YAML node. Nulls have no TOML equivalent, so they are dropped from mappings.
+fn yaml_to_value(yaml: Yaml) -> Result<Option<Value>, String> {
+    Ok(Some(match yaml {
+        Yaml::Null => return Ok(None),
+        Yaml::String(s) => Value::String(s),
+        Yaml::Integer(i) => Value::Integer(i),
+        Yaml::Real(r) => Value::Float(r.parse().map_err(|_| format!("invalid number {}", r))?),
+        Yaml::Boolean(b) => Value::Boolean(b),
+        Yaml::Array(a) => Value::Array(
+            a.into_iter()
+                .map(|v| yaml_to_value(v)?.ok_or_else(|| "null values in lists".to_string()))
+                .collect::<Result<_, _>>()?,
+        ),
+        Yaml::Hash(h) => {
+            let mut table = Map::new();
+            for (k, v) in h {
+                let k = match k {
+                    Yaml::String(s) => s,
+                    Yaml::Integer(i) => i.to_string(),
+                    Yaml::Boolean(b) => b.to_string(),
+                    k => return Err(format!("unsupported key {:?}", k)),
+                };
+                if let Some(v) = yaml_to_value(v)? {
+                    table.insert(k, v);
+                }
+            }
+            Value::Table(table)
+        }
+        Yaml::Alias(_) => return Err("aliases are not supported".to_string()),
+        Yaml::BadValue => return Err("invalid value".to_string()),
+    }))
+}
+
+/// Converts a JSON node. Nulls have no TOML equivalent, so they are dropped from objects.
+fn json_to_value(json: serde_json::Value) -> Result<Option<Value>, String> {
+    Ok(Some(match json {
+        serde_json::Value::Null => return Ok(None),
+        serde_json::Value::String(s) => Value::String(s),
+        serde_json::Value::Bool(b) => Value::Boolean(b),
+        serde_json::Value::Number(n) => match (n.as_i64(), n.as_f64()) {
+            (Some(i), _) => Value::Integer(i),
+            (None, Some(f)) => Value::Float(f),
+            _ => return Err(format!("unsupported number {}", n)),
+        },
+        serde_json::Value::Array(a) => Value::Array(
+            a.into_iter()
+                .map(|v| json_to_value(v)?.ok_or_else(|| "null values in lists".to_string()))
+                .collect::<Result<_, _>>()?,
+        ),
+        serde_json::Value::Object(o) => {
+            let mut table = Map::new();
+            for (k, v) in o {
+                if let Some(v) = json_to_value(v)? {
+                    table.insert(k, v);
+                }
+            }
+            Value::Table(table)
+        }
+    }))
+}
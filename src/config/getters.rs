@@ -1,9 +1,8 @@
-use super::{Config, OVERRIDE_FILEPATH};
-use crate::cmd::read_with_dir_and_env;
+use super::{as_expression, secrets::as_encrypted, Config, Error, OVERRIDE_FILEPATH};
 use convert_case::{Case, Casing};
 use core::panic;
 use std::{
-    collections::HashMap,
+    collections::{BTreeSet, HashMap},
     path::{Path, PathBuf},
 };
 use toml::{
@@ -13,18 +12,63 @@ use toml::{
 
 // Getters
 impl Config {
+    /// Returns the value of `key` in the deep merge of all layers
     pub fn get(&self, key: impl AsRef<str>) -> Option<&Value> {
-        self.sorted_filepaths()
-            .into_iter()
-            .find_map(|filepath| self.get_from_file(&key, filepath))
+        match self.merged() {
+            Value::Table(t) => Self::get_from_table(t, key),
+            _ => None,
+        }
     }
 
+    /// Returns the merged value of `key` along with the layer with the highest precedence
+    /// that defines it
     pub fn get_with_filepath(&self, key: impl AsRef<str>) -> Option<(&Value, PathBuf)> {
+        let value = self.get(&key)?;
+        let filepath = self
+            .sorted_filepaths()
+            .into_iter()
+            .find(|filepath| self.get_from_file(&key, filepath).is_some())?;
+
+        Some((value, filepath))
+    }
+
+    /// Returns the value of `key` from every layer that defines it, ordered from highest to
+    /// lowest precedence. Unless the values are tables or arrays, the first entry wins.
+    pub fn get_all_with_filepath(&self, key: impl AsRef<str>) -> Vec<(&Value, PathBuf)> {
         self.sorted_filepaths()
             .into_iter()
-            .find_map(|filepath| self.get_from_file(&key, &filepath).map(|v| (v, filepath)))
+            .filter_map(|filepath| self.get_from_file(&key, &filepath).map(|v| (v, filepath)))
+            .collect()
+    }
+
+    /// Returns the sorted, dotted keys of all values in the merged config that are not tables
+    pub fn keys(&self) -> Vec<String> {
+        fn collect(prefix: &str, table: &Map<String, Value>, keys: &mut BTreeSet<String>) {
+            for (key, val) in table {
+                let key = match prefix.is_empty() {
+                    true => key.to_owned(),
+                    false => format!("{}.{}", prefix, key),
+                };
+                match val {
+                    Value::Table(t) => collect(&key, t, keys),
+                    _ => {
+                        keys.insert(key);
+                    }
+                }
+            }
+        }
+
+        let mut keys = BTreeSet::new();
+        if let Value::Table(t) = self.merged() {
+            collect("", t, &mut keys);
+        }
+
+        keys.into_iter().collect()
     }
 
+    /// Returns the value of `key` in the layer with the highest precedence that defines it, so
+    /// that changes end up in that layer. Unlike with `get`, tables and arrays are not merged
+    /// with the ones of other layers.
     pub fn get_mut(&mut self, key: impl AsRef<str>) -> Option<&mut Value> {
         for filepath in self.sorted_filepaths() {
             match self.get_from_file_mut(&key, &filepath) {
@@ -35,6 +79,7 @@ impl Config {
         None
     }
 
+    /// Like `get_mut`, along with the layer the value is taken from
     pub fn get_mut_with_filepath(&mut self, key: impl AsRef<str>) -> Option<(&mut Value, PathBuf)> {
         for filepath in self.sorted_filepaths() {
             match self.get_from_file_mut(&key, &filepath) {
@@ -49,89 +94,125 @@ impl Config {
         None
     }
 
+    /// Returns the directory of the config file with the highest precedence
+    pub fn config_dir(&self) -> Option<PathBuf> {
+        self.layers
+            .iter()
+            .find(|p| p.is_file())
+            .and_then(|p| p.parent())
+            .map(Path::to_path_buf)
+    }
+
+    /// Returns all environment variables defined in the config, including exposed parameters.
+    /// Expressions are evaluated on the first call only, subsequent calls return cached values.
     pub fn get_envs(&self) -> HashMap<String, String> {
+        self.try_get_envs().unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Like `get_envs`, but returns an error if a value can not be decrypted or evaluated
+    pub fn try_get_envs(&self) -> Result<HashMap<String, String>, Error> {
+        if let Some(envs) = self.envs.get() {
+            return Ok(envs.clone());
+        }
+
+        let envs = self.resolve_expression_values(&self.get_unresolved_envs(true)?)?;
+        Ok(self.envs.get_or_init(|| envs).clone())
+    }
+
+    /// Like `try_get_envs`, but leaves out encrypted values instead of decrypting them. The
+    /// result is not cached, as it differs from the one of `get_envs`.
+    pub(crate) fn get_envs_without_decrypting(&self) -> Result<HashMap<String, String>, Error> {
+        self.resolve_expression_values(&self.get_unresolved_envs(false)?)
+    }
+
+    fn get_unresolved_envs(
+        &self,
+        decrypt: bool,
+    ) -> Result<HashMap<String, (String, PathBuf)>, Error> {
         let mut envs: HashMap<String, (String, PathBuf)> = HashMap::new();
 
-        envs.extend(
-            self.get_merged_tables("env")
-                .into_iter()
-                .map(|(k, (v, p))| match v {
-                    Value::String(s) => (k, (s, p)),
-                    x => panic!("value for {k} is not a string, found {:?}", x),
-                }),
-        );
-
-        envs.extend(
-            self.get_merged_tables("parameters")
-                .into_iter()
-                .filter_map(|(k, (v, p))| match v {
-                    Value::Table(t) => match (t.get("value"), t.get("expose")) {
-                        (Some(Value::String(s)), Some(Value::Boolean(e))) if e == &true => Some((
-                            format!("AWSX_PARAMETER_{}", k.to_case(Case::UpperSnake)),
-                            (s.to_owned(), p),
-                        )),
-                        _ => None,
-                    },
-                    _ => None,
-                }),
-        );
-
-        self.resolve_expression_values(&mut envs);
-
-        envs.into_iter().map(|(k, (v, _))| (k, v)).collect()
-    }
-
-    fn resolve_expression_values(&self, envs: &mut HashMap<String, (String, PathBuf)>) {
-        let cleaned_envs = envs
-            .clone()
-            .into_iter()
-            .map(|(k, (v, _))| (k, v))
-            .collect::<HashMap<_, _>>();
+        for (k, (v, p)) in self.get_merged_tables("env") {
+            let s = match v {
+                Value::String(s) => s,
+                x if !decrypt && as_encrypted(&x).is_some() => continue,
+                x => match self.decrypt(format!("env.{}", k), &x)? {
+                    Some(s) => s,
+                    None => {
+                        return Err(Error::Deserialize {
+                            key: format!("env.{}", k),
+                            path: p.to_string_lossy().to_string(),
+                            msg: format!("expected a string, found {}", x),
+                        })
+                    }
+                },
+            };
+            let p = self.defining_file(format!("env.{}", k), p);
+            envs.insert(k, (s, p));
+        }
 
-        for (key, (val, config_path)) in envs.clone().into_iter() {
-            if val.starts_with("{{") && val.ends_with("}}") {
-                let exp = val[2..val.len() - 2].trim();
-                let workdir = config_path.parent().expect("has parent");
+        for (k, (v, p)) in self.get_merged_tables("parameters") {
+            let (v, e) = match &v {
+                Value::Table(t) => (t.get("value"), t.get("expose")),
+                _ => continue,
+            };
+            let v = match (v, e) {
+                (Some(v), Some(Value::Boolean(true))) => v,
+                _ => continue,
+            };
+
+            let s = match v {
+                Value::String(s) => s.to_owned(),
+                v if !decrypt && as_encrypted(v).is_some() => continue,
+                v => match self.decrypt(format!("parameters.{}.value", k), v)? {
+                    Some(s) => s,
+                    None => continue,
+                },
+            };
+            let p = self.defining_file(format!("parameters.{}.value", k), p);
+            envs.insert(
+                format!("AWSX_PARAMETER_{}", k.to_case(Case::UpperSnake)),
+                (s, p),
+            );
+        }
 
-                let val = read_with_dir_and_env(exp, workdir, &cleaned_envs, self)
-                    .expect("valid expression");
-                envs.entry(key).and_modify(|(v, _)| *v = val);
-            }
+        Ok(envs)
+    }
+
+    /// The process environment with the plain string values of the `env` table on top, without
+    /// evaluating expressions or decrypting values. Used for aws calls that are needed to
+    /// resolve the config itself.
+    pub(crate) fn get_static_envs(&self) -> HashMap<String, String> {
+        let mut env = std::env::vars().collect::<HashMap<_, _>>();
+
+        if let Some(Value::Table(t)) = self.get("env") {
+            env.extend(t.iter().filter_map(|(k, v)| match v {
+                Value::String(s) if as_expression(s).is_none() => {
+                    Some((k.to_owned(), s.to_owned()))
+                }
+                _ => None,
+            }));
         }
+
+        env
     }
 
+    /// Returns the entries of the merged table at `key`, each along with the layer with the
+    /// highest precedence that defines it
     pub(crate) fn get_merged_tables(
         &self,
         key: impl AsRef<str>,
     ) -> HashMap<String, (Value, PathBuf)> {
-        self.sorted_filepaths()
-            .into_iter()
-            .rev()
-            .map(|filepath| {
-                self.get_from_file(&key, &filepath)
-                    .map(|v| {
-                        (
-                            v.as_table()
-                                .unwrap_or_else(|| panic!("key {} should be a table", key.as_ref()))
-                                .to_owned(),
-                            filepath,
-                        )
-                    })
-                    .unwrap_or_default()
-            })
-            .map(|(table, filepath)| {
-                table
-                    .into_iter()
-                    .map(|(key, val)| (key, (val, filepath.clone())))
-                    .collect::<HashMap<_, _>>()
-            })
-            .reduce(|mut accum, item| {
-                item.into_iter().for_each(|(k, v)| {
-                    accum.insert(k, v);
-                });
-                accum
-            })
-            .unwrap_or_default()
+        match self.get(&key) {
+            Some(Value::Table(t)) => t
+                .iter()
+                .filter_map(|(k, _)| {
+                    self.get_with_filepath(format!("{}.{}", key.as_ref(), k))
+                        .map(|(v, filepath)| (k.to_owned(), (v.to_owned(), filepath)))
+                })
+                .collect(),
+            Some(_) => panic!("key {} should be a table", key.as_ref()),
+            None => HashMap::new(),
+        }
     }
 
     pub(crate) fn get_from_file(
@@ -140,11 +221,7 @@ impl Config {
         filepath: impl AsRef<Path>,
     ) -> Option<&Value> {
         let (key, sub_keys) = Self::split_key_once(key.as_ref());
-        let filepath = if filepath.as_ref().to_string_lossy() == OVERRIDE_FILEPATH {
-            filepath.as_ref().to_owned()
-        } else {
-            filepath.as_ref().canonicalize().ok()?
-        };
+        let filepath = self.layer_path(filepath)?;
         let val = self.file_map.get(&filepath)?.get(key)?;
         match (sub_keys.is_empty(), val) {
             (false, Value::Table(t)) => Self::get_from_table(t, sub_keys),
@@ -171,12 +248,10 @@ impl Config {
         key: impl AsRef<str>,
         filepath: impl AsRef<Path>,
     ) -> Option<&mut Value> {
+        self.invalidate_cache();
+
         let (key, sub_keys) = Self::split_key_once(key.as_ref());
-        let filepath = if filepath.as_ref().to_string_lossy() == OVERRIDE_FILEPATH {
-            filepath.as_ref().to_owned()
-        } else {
-            filepath.as_ref().canonicalize().ok()?
-        };
+        let filepath = self.layer_path(filepath)?;
         let val = self.file_map.get_mut(&filepath)?.get_mut(key)?;
         if sub_keys.is_empty() {
             Some(val)
@@ -208,6 +283,8 @@ impl Config {
         &mut self,
         filepath: impl AsRef<Path>,
     ) -> &mut Map<String, Value> {
+        self.invalidate_cache();
+
         self.file_map
             .entry(filepath.as_ref().to_owned())
             .or_insert_with(|| Value::Table(Map::new()))
@@ -222,18 +299,21 @@ impl Config {
         }
     }
 
-    pub(crate) fn sorted_filepaths(&self) -> Vec<PathBuf> {
-        let mut filepaths = self
-            .file_map
-            .keys()
-            .map(ToOwned::to_owned)
-            .filter(|e| e.to_string_lossy() != OVERRIDE_FILEPATH)
-            .collect::<Vec<_>>();
-
-        filepaths.sort_by(|a, b| b.partial_cmp(a).unwrap());
-        filepaths.insert(0, PathBuf::from(OVERRIDE_FILEPATH));
+    /// Returns the key under which the layer at `filepath` is stored. Pseudo layers like the
+    /// overrides are stored as is, files are stored with their absolute path.
+    pub(crate) fn layer_path(&self, filepath: impl AsRef<Path>) -> Option<PathBuf> {
+        if self.file_map.contains_key(filepath.as_ref()) {
+            Some(filepath.as_ref().to_owned())
+        } else {
+            filepath.as_ref().canonicalize().ok()
+        }
+    }
 
-        filepaths
+    pub(crate) fn sorted_filepaths(&self) -> Vec<PathBuf> {
+        [PathBuf::from(OVERRIDE_FILEPATH)]
+            .into_iter()
+            .chain(self.layers.iter().cloned())
+            .collect()
     }
 }
 
@@ -352,7 +432,7 @@ mod tests {
         let v = config.get_from_file("non_existent", &path);
         assert_eq!(v, None);
 
-        let v = config.get_from_file("var_a", &path.join("invalid"));
+        let v = config.get_from_file("var_a", path.join("invalid"));
         assert_eq!(v, None);
 
         let v = config
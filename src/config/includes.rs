@@ -0,0 +1,106 @@
+use super::{Config, Error};
+use std::path::{Path, PathBuf};
+use toml::Value;
+
+const INCLUDE_KEY: &str = "include";
+
+/// Includes
+impl Config {
+    /// Loads the file at `filepath` as the layer with the lowest precedence so far, followed by
+    /// the files listed in its `include` entry. Included files rank below the file including
+    /// them, and later entries of the list rank above earlier ones.
+    pub(crate) fn push_layer(&mut self, filepath: impl AsRef<Path>) -> Result<(), Error> {
+        self.push_layer_with_includes(filepath.as_ref(), &mut Vec::new())
+    }
+
+    fn push_layer_with_includes(
+        &mut self,
+        filepath: &Path,
+        chain: &mut Vec<PathBuf>,
+    ) -> Result<(), Error> {
+        if chain.iter().any(|p| p == filepath) {
+            chain.push(filepath.to_owned());
+            let chain = chain
+                .iter()
+                .map(|p| p.to_string_lossy())
+                .collect::<Vec<_>>()
+                .join(" -> ");
+            return Err(Error::load_error(
+                filepath,
+                &format!("cyclic include: {}", chain),
+            ));
+        }
+
+        // a file that is included multiple times only keeps its highest precedence
+        if self.layers.iter().any(|p| p == filepath) {
+            return Ok(());
+        }
+
+        let mut file = Config::load_one(filepath)?;
+        Self::import_env_files(&mut file, filepath)?;
+        let includes = Self::included_paths(&file, filepath)?;
+
+        self.file_map.insert(filepath.to_owned(), file);
+        self.layers.push(filepath.to_owned());
+        self.invalidate_cache();
+
+        chain.push(filepath.to_owned());
+        for include in includes.into_iter().rev() {
+            self.push_layer_with_includes(&include, chain)?;
+        }
+        chain.pop();
+
+        Ok(())
+    }
+
+    /// Returns the absolute paths of all files listed in the `include` entry of `file`.
+    /// Relative paths are resolved from the directory of `filepath`, a leading `~` is replaced
+    /// with the home directory.
+    fn included_paths(file: &Value, filepath: &Path) -> Result<Vec<PathBuf>, Error> {
+        let includes = match file.get(INCLUDE_KEY) {
+            None => return Ok(Vec::new()),
+            Some(Value::String(s)) => vec![s.as_str()],
+            Some(Value::Array(a)) => a
+                .iter()
+                .map(|v| {
+                    v.as_str().ok_or_else(|| {
+                        Error::load_error(
+                            filepath,
+                            &format!("'{}' entries should be strings, found {}", INCLUDE_KEY, v),
+                        )
+                    })
+                })
+                .collect::<Result<_, _>>()?,
+            Some(v) => {
+                return Err(Error::load_error(
+                    filepath,
+                    &format!(
+                        "'{}' should be a path or a list of paths, found {}",
+                        INCLUDE_KEY, v
+                    ),
+                ))
+            }
+        };
+
+        let dir = filepath.parent().expect("has parent");
+
+        includes
+            .into_iter()
+            .map(|include| {
+                let path = match include.strip_prefix("~/") {
+                    Some(rest) => home_dir(filepath)?.join(rest),
+                    None => dir.join(include),
+                };
+                path.canonicalize().map_err(|e| {
+                    Error::load_error(filepath, &format!("could not include {:?}: {}", include, e))
+                })
+            })
+            .collect()
+    }
+}
+
+pub(super) fn home_dir(filepath: &Path) -> Result<PathBuf, Error> {
+    std::env::var_os("HOME")
+        .map(PathBuf::from)
+        .ok_or_else(|| Error::load_error(filepath, "could not expand '~', HOME is not set"))
+}
@@ -1,4 +1,4 @@
-use super::{Config, Error, Options};
+use super::{formats::Format, Config, Error, Options};
 use std::{
     collections::HashMap,
     path::{Path, PathBuf},
@@ -16,48 +16,111 @@ impl Config {
     pub fn new() -> Config {
         Config {
             file_map: HashMap::new(),
+            layers: Vec::new(),
+            merged: Default::default(),
+            override_sources: HashMap::new(),
+            environment_sources: HashMap::new(),
+            environment: None,
+            envs: Default::default(),
+            expressions: Default::default(),
+            locked: HashMap::new(),
         }
     }
 
+    /// In nested mode, loads the config files in the directory of `config_path` and all of its
+    /// parents up to the project root, from the closest to the farthest. Otherwise only the
+    /// file at `config_path` is loaded.
     pub fn from_path(config_path: impl AsRef<Path>, options: Options) -> Result<Config, Error> {
-        let config_path = config_path
-            .as_ref()
-            .canonicalize()
-            .map_err(|_| Error::load_error(config_path, "could not make an absolute path"))?;
+        let config_path = canonicalize(config_path)?;
 
-        let mut files: HashMap<PathBuf, Value> = HashMap::new();
+        let mut config = Config::new();
 
         if options.nested {
-            let project_root = options.get_project_root().map_err(|e| {
-                Error::load_error(&config_path, &format!("could not find project root: {}", e))
-            })?;
-
-            let mut config_path = config_path;
-            while config_path.pop() {
-                let new_path = &config_path.join(&options.filename);
-                if let Ok(m) = std::fs::metadata(new_path) {
-                    if m.is_file() {
-                        let file = Config::load_one(new_path)?;
-                        files.insert(new_path.clone(), file);
-                    }
-                }
+            config.push_nested_layers(&config_path, &options)?;
+        } else {
+            config.push_layer_with_local(&config_path)?;
+        }
+
+        config.finish_loading(&options)?;
+
+        Ok(config)
+    }
+
+    /// Loads all given config files, later paths take precedence over earlier ones. A directory
+    /// stands for the config files in it. In nested mode the config files of their parent
+    /// directories up to the project root are loaded as well, below all given paths.
+    pub fn from_paths(
+        config_paths: impl IntoIterator<Item = impl AsRef<Path>>,
+        options: Options,
+    ) -> Result<Config, Error> {
+        let config_paths = config_paths
+            .into_iter()
+            .map(canonicalize)
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut config = Config::new();
 
-                if config_path.ends_with(&project_root) {
-                    break;
+        for config_path in config_paths.iter().rev() {
+            if config_path.is_dir() {
+                for filepath in options.config_files_in(config_path) {
+                    config.push_layer_with_local(filepath)?;
                 }
+            } else {
+                config.push_layer_with_local(config_path)?;
+            }
+        }
+
+        // files that were loaded already keep their higher precedence
+        if options.nested {
+            for config_path in config_paths.iter().rev() {
+                config.push_nested_layers(config_path, &options)?;
             }
-        } else {
-            files.insert(config_path.clone(), Config::load_one(&config_path)?);
         }
 
-        Ok(Config { file_map: files })
+        config.finish_loading(&options)?;
+
+        Ok(config)
     }
 
-    fn load_one(config_path: impl AsRef<Path>) -> Result<Value, Error> {
+    /// Pushes the config files of all parent directories of `config_path` up to the project root
+    fn push_nested_layers(&mut self, config_path: &Path, options: &Options) -> Result<(), Error> {
+        let project_root = options.get_project_root().map_err(|e| {
+            Error::load_error(config_path, &format!("could not find project root: {}", e))
+        })?;
+
+        let mut config_path = config_path.to_owned();
+        while config_path.pop() {
+            for filepath in options.config_files_in(&config_path) {
+                self.push_layer_with_local(filepath)?;
+            }
+
+            if config_path.ends_with(&project_root) {
+                break;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Adds the layers that depend on the loaded files and validates the result
+    fn finish_loading(&mut self, options: &Options) -> Result<(), Error> {
+        self.push_remote_layers(options)?;
+        self.array_strategies()?;
+
+        if let Some(environment) = &options.environment {
+            self.apply_environment(environment)?;
+        }
+
+        if !options.refresh {
+            self.load_lock()?;
+        }
+
+        Ok(())
+    }
+
+    pub(crate) fn load_one(config_path: impl AsRef<Path>) -> Result<Value, Error> {
         match std::fs::read_to_string(config_path.as_ref()) {
-            Ok(bytes) => bytes
-                .parse::<Value>()
-                .map_err(|e| Error::load_error(config_path, &format!("Invalid TOML: {}", e))),
+            Ok(contents) => Format::from_path(&config_path).parse(&contents, config_path),
             Err(e) => Err(Error::load_error(config_path, &e.to_string())),
         }
     }
@@ -68,3 +131,10 @@ impl Config {
         todo!()
     }
 }
+
+fn canonicalize(config_path: impl AsRef<Path>) -> Result<PathBuf, Error> {
+    config_path
+        .as_ref()
+        .canonicalize()
+        .map_err(|_| Error::load_error(config_path, "could not make an absolute path"))
+}
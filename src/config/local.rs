@@ -0,0 +1,89 @@
+use super::Config;
+use duct::cmd;
+use std::path::{Path, PathBuf};
+
+/// Local overrides
+impl Config {
+    /// Pushes the local sibling of `filepath`, i.e. `config.local.toml` next to `config.toml`,
+    /// if there is one, followed by `filepath` itself. Local files are meant to hold personal
+    /// settings, so a warning is printed if one is tracked by git.
+    pub(crate) fn push_layer_with_local(
+        &mut self,
+        filepath: impl AsRef<Path>,
+    ) -> Result<(), super::Error> {
+        if let Some(local_path) = local_path(&filepath) {
+            if local_path.is_file() {
+                if is_tracked_by_git(&local_path) {
+                    eprintln!(
+                        "warning: {:?} is tracked by git, personal overrides should be listed in .gitignore",
+                        local_path
+                    );
+                }
+                self.push_layer(&local_path)?;
+            }
+        }
+
+        self.push_layer(filepath)
+    }
+}
+
+/// Inserts `.local` before the extension of `filepath`, unless it is a local file already
+pub(crate) fn local_path(filepath: impl AsRef<Path>) -> Option<PathBuf> {
+    let filepath = filepath.as_ref();
+    let stem = filepath.file_stem()?.to_str()?;
+
+    if stem.ends_with(".local") {
+        return None;
+    }
+
+    let filename = match filepath.extension().and_then(|e| e.to_str()) {
+        Some(extension) => format!("{}.local.{}", stem, extension),
+        None => format!("{}.local", stem),
+    };
+
+    Some(filepath.with_file_name(filename))
+}
+
+/// Returns `false` if the file is not tracked, or if it is not part of a git repository at all
+fn is_tracked_by_git(filepath: &Path) -> bool {
+    let (dir, filename) = match (filepath.parent(), filepath.file_name()) {
+        (Some(dir), Some(filename)) => (dir, filename),
+        _ => return false,
+    };
+
+    cmd!("git", "ls-files", "--error-unmatch", filename)
+        .dir(dir)
+        .stdout_null()
+        .stderr_null()
+        .unchecked()
+        .run()
+        .map(|output| output.status.success())
+        .unwrap_or(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_local_path() {
+        assert_eq!(
+            local_path("a/config.toml"),
+            Some(PathBuf::from("a/config.local.toml"))
+        );
+        assert_eq!(
+            local_path("a/config.yaml"),
+            Some(PathBuf::from("a/config.local.yaml"))
+        );
+        assert_eq!(local_path("a/config.local.toml"), None);
+    }
+
+    #[test]
+    fn test_is_tracked_by_git() {
+        let tracked = PathBuf::from("Cargo.toml").canonicalize().unwrap();
+        assert!(is_tracked_by_git(&tracked));
+
+        let untracked = std::env::temp_dir().join("awsx-untracked.toml");
+        assert!(!is_tracked_by_git(&untracked));
+    }
+}
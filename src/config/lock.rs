@@ -0,0 +1,199 @@
+use super::{as_expression, Config, Error};
+use std::{
+    collections::HashMap,
+    path::{Component, Path, PathBuf},
+};
+use toml::{value::Map, Value};
+
+const LOCK_FILENAME: &str = "awsx.lock";
+const LOCK_HEADER: &str =
+    "# Results of config expressions, written by `awsx lock`. Run it again to update them.\n\
+     # `inputs` is a hash of the environment and the variables an expression was evaluated with.\n\n";
+
+/// Lock
+impl Config {
+    /// The lock file is kept next to the config file with the highest precedence
+    pub fn lock_path(&self) -> Option<PathBuf> {
+        self.config_dir().map(|dir| dir.join(LOCK_FILENAME))
+    }
+
+    /// Evaluates the expressions of the env table, the parameters and the applied environment
+    /// again and writes their results to the lock file, returning its path. Expressions of the
+    /// overrides are not locked.
+    pub fn lock(&mut self) -> Result<PathBuf, Error> {
+        let lock_path = self
+            .lock_path()
+            .ok_or_else(|| Error::load_error(LOCK_FILENAME, "no config file to put it next to"))?;
+
+        self.locked.clear();
+        self.invalidate_cache();
+
+        // evaluating the env table locks its expressions, the aws settings are not needed for that
+        let envs = self
+            .try_get_envs()?
+            .into_iter()
+            .chain(std::env::vars())
+            .collect::<HashMap<_, _>>();
+
+        // only the values that commands resolve, not the definitions of other environments
+        let keys = self.keys().into_iter().filter(|k| {
+            k.starts_with("parameters.")
+                || self
+                    .environment_sources
+                    .keys()
+                    .any(|(_, key)| key == k && !k.starts_with("env."))
+        });
+
+        for key in keys {
+            if let Some((Value::String(s), filepath)) = self.get_with_filepath(&key) {
+                let filepath = self.defining_file(&key, filepath);
+                if let Some(exp) = as_expression(s) {
+                    self.evaluate_expression(exp, filepath, &envs)
+                        .map_err(|e| Error::Evaluate {
+                            key: key.to_owned(),
+                            msg: e.to_string(),
+                        })?;
+                }
+            }
+        }
+
+        let lock_dir = lock_path.parent().expect("has parent");
+        let mut entries = self
+            .expressions
+            .borrow()
+            .iter()
+            .filter(|((workdir, ..), _)| workdir.is_absolute())
+            .map(|((workdir, expression, inputs), value)| {
+                let dir = relative_path(workdir, lock_dir);
+                (
+                    dir.to_string_lossy().to_string(),
+                    expression.to_owned(),
+                    inputs.to_owned(),
+                    value.to_owned(),
+                )
+            })
+            .collect::<Vec<_>>();
+        entries.sort();
+
+        let entries = entries
+            .into_iter()
+            .map(|(dir, expression, inputs, value)| {
+                let mut entry = Map::new();
+                entry.insert("dir".to_owned(), Value::String(dir));
+                entry.insert("expression".to_owned(), Value::String(expression));
+                entry.insert("inputs".to_owned(), Value::String(inputs));
+                entry.insert("value".to_owned(), Value::String(value));
+                Value::Table(entry)
+            })
+            .collect();
+
+        let mut lock = Map::new();
+        lock.insert("expressions".to_owned(), Value::Array(entries));
+
+        let contents = toml::to_string(&Value::Table(lock))
+            .map_err(|e| Error::write_error(&lock_path, &e.to_string()))?;
+        std::fs::write(&lock_path, format!("{}{}", LOCK_HEADER, contents))
+            .map_err(|e| Error::write_error(&lock_path, &e.to_string()))?;
+
+        Ok(lock_path)
+    }
+
+    /// Loads the lock file, if there is one, so that its values are used instead of
+    /// evaluating the same expressions again
+    pub(crate) fn load_lock(&mut self) -> Result<(), Error> {
+        let lock_path = match self.lock_path().filter(|p| p.is_file()) {
+            Some(lock_path) => lock_path,
+            None => return Ok(()),
+        };
+        let lock_dir = lock_path.parent().expect("has parent");
+
+        let invalid = |msg: &str| Error::load_error(&lock_path, msg);
+
+        let entries = match Config::load_one(&lock_path)?.get("expressions") {
+            None => Vec::new(),
+            Some(Value::Array(a)) => a.to_owned(),
+            Some(_) => return Err(invalid("'expressions' should be a list")),
+        };
+
+        for entry in entries {
+            let field = |name: &str| {
+                entry
+                    .get(name)
+                    .and_then(Value::as_str)
+                    .map(ToOwned::to_owned)
+                    .ok_or_else(|| invalid(&format!("every entry needs a '{}' string", name)))
+            };
+            let workdir = normalize(&lock_dir.join(field("dir")?));
+
+            self.locked.insert(
+                (workdir, field("expression")?, field("inputs")?),
+                field("value")?,
+            );
+        }
+
+        Ok(())
+    }
+}
+
+/// Returns `path` relative to `base`, both of which are absolute
+fn relative_path(path: &Path, base: &Path) -> PathBuf {
+    let path = path.components().collect::<Vec<_>>();
+    let base = base.components().collect::<Vec<_>>();
+    let common = path.iter().zip(&base).take_while(|(a, b)| a == b).count();
+
+    let relative = std::iter::repeat_n(Component::ParentDir, base.len() - common)
+        .chain(path[common..].iter().copied())
+        .collect::<PathBuf>();
+
+    match relative.as_os_str().is_empty() {
+        true => PathBuf::from("."),
+        false => relative,
+    }
+}
+
+/// Removes `.` and `..` components without touching the file system
+fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            c => normalized.push(c),
+        }
+    }
+    normalized
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_relative_path() {
+        let base = Path::new("/project/sub");
+
+        assert_eq!(
+            relative_path(Path::new("/project/sub"), base),
+            PathBuf::from(".")
+        );
+        assert_eq!(
+            relative_path(Path::new("/project/sub/a"), base),
+            PathBuf::from("a")
+        );
+        assert_eq!(
+            relative_path(Path::new("/project"), base),
+            PathBuf::from("..")
+        );
+        assert_eq!(
+            relative_path(Path::new("/other/b"), base),
+            PathBuf::from("../../other/b")
+        );
+        assert_eq!(
+            normalize(&base.join("../../other/b")),
+            PathBuf::from("/other/b")
+        );
+        assert_eq!(normalize(&base.join(".")), PathBuf::from("/project/sub"));
+    }
+}
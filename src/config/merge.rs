@@ -0,0 +1,176 @@
+use super::{Config, Error};
+use std::collections::HashMap;
+use toml::{value::Map, Value};
+
+const ARRAY_STRATEGIES_KEY: &str = "merge.arrays";
+
+/// How arrays that are defined in multiple layers are combined
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum ArrayMerge {
+    /// The array of the layer with the highest precedence is used as is
+    #[default]
+    Replace,
+
+    /// Arrays of all layers are concatenated, starting with the lowest precedence
+    Append,
+
+    /// Tables with the same value in the given field are merged, all others are appended
+    MergeByKey(String),
+}
+
+impl TryFrom<&Value> for ArrayMerge {
+    type Error = String;
+
+    fn try_from(value: &Value) -> Result<Self, Self::Error> {
+        match value {
+            Value::String(s) if s == "replace" => Ok(ArrayMerge::Replace),
+            Value::String(s) if s == "append" => Ok(ArrayMerge::Append),
+            Value::Table(t) => match t.get("by_key") {
+                Some(Value::String(field)) if t.len() == 1 => {
+                    Ok(ArrayMerge::MergeByKey(field.to_owned()))
+                }
+                _ => Err(format!(
+                    "expected {{ by_key = \"<field>\" }}, found {}",
+                    value
+                )),
+            },
+            v => Err(format!(
+                "expected \"replace\", \"append\" or {{ by_key = \"<field>\" }}, found {}",
+                v
+            )),
+        }
+    }
+}
+
+/// Array merge strategies by dotted key, as configured in `[merge.arrays]`.
+/// The `default` entry applies to all arrays without their own entry.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct ArrayStrategies(HashMap<String, ArrayMerge>);
+
+impl ArrayStrategies {
+    fn get(&self, key: &str) -> &ArrayMerge {
+        self.0
+            .get(key)
+            .or_else(|| self.0.get("default"))
+            .unwrap_or(&ArrayMerge::Replace)
+    }
+}
+
+/// Merging
+impl Config {
+    /// The deep merge of all layers, computed on first access
+    pub(crate) fn merged(&self) -> &Value {
+        self.merged.get_or_init(|| {
+            let strategies = self.array_strategies().unwrap_or_else(|e| panic!("{}", e));
+
+            self.sorted_filepaths()
+                .into_iter()
+                .rev()
+                .filter_map(|filepath| self.file_map.get(&filepath))
+                .fold(Value::Table(Map::new()), |mut merged, layer| {
+                    merge_value(&mut merged, layer.clone(), "", &strategies);
+                    merged
+                })
+        })
+    }
+
+    /// How many of the layers returned by `get_all_with_filepath` make up the merged value of
+    /// `key`, starting with the first one. Tables are merged from all layers down to the first
+    /// one with another kind of value, arrays too unless they are replaced.
+    pub fn contributing_layers(&self, key: impl AsRef<str>) -> usize {
+        let layers = self.get_all_with_filepath(&key);
+        let first = match layers.first() {
+            Some((first, _)) => first,
+            None => return 0,
+        };
+
+        let merges = match first {
+            Value::Table(_) => true,
+            Value::Array(_) => self
+                .array_strategies()
+                .is_ok_and(|s| s.get(key.as_ref()) != &ArrayMerge::Replace),
+            _ => false,
+        };
+
+        match merges {
+            true => layers
+                .iter()
+                .take_while(|(value, _)| value.same_type(first))
+                .count(),
+            false => 1,
+        }
+    }
+
+    /// Collects the `[merge.arrays]` entries of all layers, without merging them first
+    pub(crate) fn array_strategies(&self) -> Result<ArrayStrategies, Error> {
+        let mut strategies = HashMap::new();
+
+        for filepath in self.sorted_filepaths().into_iter().rev() {
+            match self.get_from_file(ARRAY_STRATEGIES_KEY, &filepath) {
+                Some(Value::Table(t)) => {
+                    for (key, val) in t {
+                        let strategy = ArrayMerge::try_from(val).map_err(|msg| {
+                            Error::load_error(
+                                &filepath,
+                                &format!("invalid {}.{:?}: {}", ARRAY_STRATEGIES_KEY, key, msg),
+                            )
+                        })?;
+                        strategies.insert(key.to_owned(), strategy);
+                    }
+                }
+                Some(v) => {
+                    return Err(Error::load_error(
+                        &filepath,
+                        &format!("{:?} should be a table, found {}", ARRAY_STRATEGIES_KEY, v),
+                    ))
+                }
+                None => {}
+            }
+        }
+
+        Ok(ArrayStrategies(strategies))
+    }
+}
+
+/// Recursively merges `overlay` into `base`. Tables are merged key by key, arrays according to
+/// the strategy configured for their dotted `key` and all other values are replaced.
+pub(crate) fn merge_value(
+    base: &mut Value,
+    overlay: Value,
+    key: &str,
+    strategies: &ArrayStrategies,
+) {
+    match (base, overlay) {
+        (Value::Table(base), Value::Table(overlay)) => {
+            for (k, v) in overlay {
+                let sub_key = match key.is_empty() {
+                    true => k.to_owned(),
+                    false => format!("{}.{}", key, k),
+                };
+                match base.get_mut(&k) {
+                    Some(existing) => merge_value(existing, v, &sub_key, strategies),
+                    None => {
+                        base.insert(k, v);
+                    }
+                }
+            }
+        }
+        (Value::Array(base), Value::Array(overlay)) => match strategies.get(key) {
+            ArrayMerge::Replace => *base = overlay,
+            ArrayMerge::Append => base.extend(overlay),
+            ArrayMerge::MergeByKey(field) => {
+                for item in overlay {
+                    let existing = item.get(field).and_then(|id| {
+                        base.iter_mut()
+                            .find(|existing| existing.get(field) == Some(id))
+                    });
+                    match existing {
+                        Some(existing) => merge_value(existing, item, key, strategies),
+                        None => base.push(item),
+                    }
+                }
+            }
+        },
+        (base, overlay) => *base = overlay,
+    }
+}
@@ -1,18 +1,60 @@
+pub use self::cli::*;
+pub use self::diff::Change;
 pub use self::error::Error;
-pub use self::options::Options;
-use std::{collections::HashMap, path::PathBuf};
+pub(crate) use self::expressions::as_expression;
+pub use self::merge::ArrayMerge;
+pub use self::options::{Options, Subcommands};
+use std::{
+    cell::{OnceCell, RefCell},
+    collections::HashMap,
+    path::PathBuf,
+};
 use toml::Value;
 
+pub mod cli;
 mod error;
 mod options;
 
+mod diff;
+mod dotenv;
+mod environments;
+mod expressions;
+mod formats;
 mod getters;
+mod includes;
 mod init;
+mod local;
+mod lock;
+mod merge;
+mod overrides;
+mod persist;
+mod remote;
+mod schema;
+mod secrets;
 mod setters;
+mod typed;
 
 const OVERRIDE_FILEPATH: &str = "overrides";
 
 #[derive(Debug, Clone)]
 pub struct Config {
     file_map: HashMap<PathBuf, Value>,
+    /// Paths of all layers in `file_map` from highest to lowest precedence, without the overrides
+    layers: Vec<PathBuf>,
+    /// Deep merge of all layers, computed on first access
+    merged: OnceCell<Value>,
+    /// Describes where values in the overrides layer came from, keyed by their dotted key
+    override_sources: HashMap<String, String>,
+    /// Config files that define the values of an applied environment, keyed by the pseudo path
+    /// of the environment and their dotted key
+    environment_sources: HashMap<(PathBuf, String), PathBuf>,
+    /// Name of the applied environment
+    environment: Option<String>,
+    /// Resolved environment variables, computed on first access
+    envs: OnceCell<HashMap<String, String>>,
+    /// Results of already evaluated expressions, keyed by working directory, expression and a
+    /// hash of the applied environment and the values of the variables it references
+    expressions: RefCell<HashMap<(PathBuf, String, String), String>>,
+    /// Results of expressions pinned in the lock file, keyed like `expressions`
+    locked: HashMap<(PathBuf, String, String), String>,
 }
@@ -1,48 +1,162 @@
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+
+/// File or directory that marks the project root explicitly
+const ROOT_MARKER: &str = ".awsx-root";
 
 #[derive(Debug, Clone)]
 pub struct Options {
-    pub filename: String,
+    /// Names of the config files to look for in every directory, from highest to lowest
+    /// precedence. Files ending in `.yaml`/`.yml` or `.json` are parsed as such, all others
+    /// as TOML.
+    pub filenames: Vec<String>,
     pub nested: bool,
     pub project_root: Option<PathBuf>,
+    /// Name of an `[environments.<name>]` table that is applied on top of all config files
+    pub environment: Option<String>,
+    /// Use the cached copies of remote layers instead of fetching them
+    pub offline: bool,
+    /// Directory for cached remote layers, defaults to `$XDG_CACHE_HOME/awsx` or `~/.cache/awsx`
+    pub cache_dir: Option<PathBuf>,
+    /// Evaluate all expressions instead of using the values pinned in `awsx.lock`
+    pub refresh: bool,
 }
 
 impl Default for Options {
     fn default() -> Self {
         Self {
-            filename: String::from("config.toml"),
+            filenames: vec![
+                String::from("config.toml"),
+                String::from("config.yaml"),
+                String::from("config.json"),
+            ],
             nested: true,
             project_root: None,
+            environment: None,
+            offline: false,
+            cache_dir: None,
+            refresh: false,
         }
     }
 }
 
 impl Options {
     /// If `project_root` is `Some(path)` it will be returned as an absolute path.
-    /// If it is `None` it will find the first parent folder containing a `.git`
-    /// directory and return that as an absolute path.
+    /// If it is `None` the project root is searched from the current directory upwards,
+    /// see [`Options::find_project_root`].
     pub fn get_project_root(&self) -> Result<PathBuf, std::io::Error> {
         if let Some(project_root) = self.project_root.clone() {
             std::fs::canonicalize(project_root)
         } else {
-            let mut path = std::env::current_dir()?;
-
-            let project_root = loop {
-                if let Ok(m) = std::fs::metadata(path.join(".git")) {
-                    if m.is_dir() {
-                        break path;
-                    }
-                }
-                if path.parent().is_none() {
-                    return Err(std::io::Error::new(
-                        std::io::ErrorKind::NotFound,
-                        "could not find project root",
-                    ));
-                }
-                path.pop();
-            };
-
-            Ok(project_root)
+            Self::find_project_root(std::env::current_dir()?)
         }
     }
+
+    /// Returns the config files in `dir` from highest to lowest precedence
+    pub fn config_files_in(&self, dir: impl AsRef<Path>) -> Vec<PathBuf> {
+        self.filenames
+            .iter()
+            .map(|filename| dir.as_ref().join(filename))
+            .filter(|path| path.is_file())
+            .collect()
+    }
+
+    /// Returns `cache_dir` if it is set, or the default cache directory if it can be found
+    pub fn get_cache_dir(&self) -> Option<PathBuf> {
+        self.cache_dir.clone().or_else(|| {
+            std::env::var_os("XDG_CACHE_HOME")
+                .map(PathBuf::from)
+                .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")))
+                .map(|dir| dir.join("awsx"))
+        })
+    }
+
+    /// Returns the closest parent folder of `start` that contains a `.awsx-root` marker or a
+    /// `.git` directory or file. Submodules and worktrees have a `.git` file instead of a
+    /// directory.
+    pub fn find_project_root(start: impl AsRef<Path>) -> Result<PathBuf, std::io::Error> {
+        let start = std::fs::canonicalize(start)?;
+
+        start
+            .ancestors()
+            .find(|path| [ROOT_MARKER, ".git"].iter().any(|m| path.join(m).exists()))
+            .map(Path::to_path_buf)
+            .ok_or_else(|| {
+                std::io::Error::new(std::io::ErrorKind::NotFound, "could not find project root")
+            })
+    }
+}
+
+/// Commands to inspect and edit the loaded configuration
+#[derive(Debug, clap::Subcommand)]
+pub enum Subcommands {
+    /// Prints the value of a single key
+    Get {
+        /// Dotted path of the key, i.e. "env.AWS_PROFILE"
+        key: String,
+
+        /// Also print the file the value was taken from
+        #[clap(long, action)]
+        show_source: bool,
+    },
+
+    /// Prints every key of the merged config along with the file it was taken from
+    List {},
+
+    /// Prints every layer that defines the key and marks the ones that make up its value
+    Explain {
+        /// Dotted path of the key, i.e. "env.AWS_PROFILE"
+        key: String,
+    },
+
+    /// Sets the value of a key in a config file on disk
+    Set {
+        /// Dotted path of the key, i.e. "env.AWS_PROFILE"
+        key: String,
+
+        /// The value is parsed as TOML, i.e. `42`, `true` or `["a", "b"]`, and used as a
+        /// plain string if that fails
+        value: String,
+
+        /// Path of the config file to write to
+        #[clap(long, short = 'f')]
+        file: PathBuf,
+    },
+
+    /// Prints the keys that were added, removed or changed in the config at `b` compared to `a`
+    Diff {
+        /// Path of a config file or a directory containing one
+        a: PathBuf,
+
+        /// Path of a config file or a directory containing one
+        b: PathBuf,
+
+        /// Compare the values that expressions resolve to
+        #[clap(long, action)]
+        resolve: bool,
+
+        /// Compare and print the plaintexts of encrypted values instead of their ciphertexts
+        #[clap(long, action, requires = "resolve")]
+        decrypt: bool,
+    },
+
+    /// Prints a JSON Schema of the config files, for completion and validation in editors
+    Schema {},
+
+    /// Encrypts the value of a key and writes it back to the config file that defines it
+    Encrypt {
+        /// Dotted path of the key, i.e. "env.DB_PASSWORD"
+        key: String,
+
+        /// Encrypts this value instead of the current value of the key
+        #[clap(long)]
+        value: Option<String>,
+
+        /// Encrypts with this KMS key id or alias instead of the age key
+        #[clap(long)]
+        kms: Option<String>,
+
+        /// Path of the config file to write to, defaults to the file that defines the key
+        #[clap(long, short = 'f')]
+        file: Option<PathBuf>,
+    },
 }
@@ -0,0 +1,129 @@
+//! Values that are set from outside of the config files end up in the overrides layer, which
+//! ranks above everything else. From highest to lowest precedence a value is taken from:
+//!
+//! 1. `--set key=value` command line arguments, later arguments win
+//! 2. `AWSX__SECTION__KEY` environment variables
+//! 3. the environment selected with `--env` / `AWSX_ENV`
+//! 4. the config files, starting with the innermost directory
+
+use super::{Config, Error, OVERRIDE_FILEPATH};
+use std::path::PathBuf;
+use toml::Value;
+
+const ENV_VAR_PREFIX: &str = "AWSX__";
+const ENV_VAR_SEPARATOR: &str = "__";
+
+/// Env values end up as environment variables, so `123` or `true` are kept as strings there
+pub(super) fn override_value(key: &str, raw: impl AsRef<str>) -> Value {
+    match key.starts_with("env.") {
+        true => Value::String(raw.as_ref().to_owned()),
+        false => Config::parse_value(raw),
+    }
+}
+
+/// Overrides
+impl Config {
+    /// Sets a value for every `AWSX__SECTION__KEY` variable, i.e. `AWSX__PARAMETERS__ImageTag`
+    /// sets `parameters.ImageTag`. Each part of the name is matched case-insensitively against
+    /// the existing keys. Parts without a match are lowercased, except for the last one.
+    pub fn set_from_env_vars(
+        &mut self,
+        vars: impl IntoIterator<Item = (String, String)>,
+    ) -> Result<(), Error> {
+        let mut vars = vars
+            .into_iter()
+            .filter(|(name, _)| name.starts_with(ENV_VAR_PREFIX))
+            .collect::<Vec<_>>();
+        vars.sort();
+
+        for (name, raw) in vars {
+            let parts = name[ENV_VAR_PREFIX.len()..]
+                .split(ENV_VAR_SEPARATOR)
+                .collect::<Vec<_>>();
+            if parts.iter().any(|p| p.is_empty()) {
+                continue;
+            }
+
+            let key = self.match_existing_key(&parts);
+            let value = override_value(&key, raw);
+            self.set_with_source(key, value, name);
+        }
+
+        self.validate_overrides()
+    }
+
+    /// Sets a value for every `key=value` argument. Values are parsed as TOML and used as plain
+    /// strings if that fails. Values below `env` are always strings.
+    pub fn set_from_args(
+        &mut self,
+        args: impl IntoIterator<Item = impl AsRef<str>>,
+    ) -> Result<(), Error> {
+        for arg in args {
+            let (key, raw) = arg
+                .as_ref()
+                .split_once('=')
+                .filter(|(key, _)| !key.trim().is_empty())
+                .ok_or_else(|| Error::InvalidOverride {
+                    arg: arg.as_ref().to_owned(),
+                })?;
+
+            self.set_with_source(
+                key.trim(),
+                override_value(key.trim(), raw),
+                format!("--set {}", key.trim()),
+            );
+        }
+
+        self.validate_overrides()
+    }
+
+    /// The pseudo path that is reported as the source of values in the overrides layer
+    pub fn override_path() -> PathBuf {
+        PathBuf::from(OVERRIDE_FILEPATH)
+    }
+
+    /// Describes where a value in the overrides layer came from, if it was not set via
+    /// [`Config::set`] directly
+    pub fn override_source(&self, key: impl AsRef<str>) -> Option<&str> {
+        self.override_sources.get(key.as_ref()).map(|s| s.as_str())
+    }
+
+    /// The merged view is only computed when it is used, so settings that it depends on are
+    /// checked right away instead of failing there
+    fn validate_overrides(&self) -> Result<(), Error> {
+        self.array_strategies().map(|_| ())
+    }
+
+    fn set_with_source(&mut self, key: impl AsRef<str>, value: Value, source: impl Into<String>) {
+        self.set(&key, value);
+        self.override_sources
+            .insert(key.as_ref().to_owned(), source.into());
+    }
+
+    fn match_existing_key(&self, parts: &[&str]) -> String {
+        let mut table = self.merged().as_table();
+        let mut key = Vec::new();
+
+        for (i, part) in parts.iter().enumerate() {
+            let existing = table.and_then(|t| {
+                t.iter()
+                    .find(|(k, _)| k.eq_ignore_ascii_case(part))
+                    .map(|(k, v)| (k.to_owned(), v.as_table()))
+            });
+
+            match existing {
+                Some((k, next)) => {
+                    key.push(k);
+                    table = next;
+                }
+                None if i + 1 < parts.len() => {
+                    key.push(part.to_lowercase());
+                    table = None;
+                }
+                None => key.push(part.to_string()),
+            }
+        }
+
+        key.join(".")
+    }
+}
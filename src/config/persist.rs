@@ -0,0 +1,125 @@
+use super::{formats::Format, Config, Error};
+use std::path::Path;
+use toml::Value;
+use toml_edit::{Document, Item, Table};
+
+/// Persist
+impl Config {
+    /// Sets `key` to `value` in the config file at `filepath` and writes it back to disk.
+    /// Comments, ordering and formatting of everything else in that file are preserved.
+    /// If that file is one of the loaded layers, the loaded config is updated as well.
+    pub fn set_in_file(
+        &mut self,
+        key: impl AsRef<str>,
+        value: Value,
+        filepath: impl AsRef<Path>,
+    ) -> Result<(), Error> {
+        let filepath = filepath.as_ref();
+
+        if Format::from_path(filepath) != Format::Toml {
+            return Err(Error::write_error(
+                filepath,
+                "only TOML config files can be written",
+            ));
+        }
+
+        let mut document = match std::fs::read_to_string(filepath) {
+            Ok(s) => s
+                .parse::<Document>()
+                .map_err(|e| Error::load_error(filepath, &format!("Invalid TOML: {}", e)))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Document::new(),
+            Err(e) => return Err(Error::load_error(filepath, &e.to_string())),
+        };
+
+        insert_item(
+            document.as_table_mut(),
+            key.as_ref(),
+            to_item(value.clone()),
+        )
+        .map_err(|msg| Error::write_error(filepath, &msg))?;
+
+        std::fs::write(filepath, document.to_string())
+            .map_err(|e| Error::write_error(filepath, &e.to_string()))?;
+
+        if let Some(layer) = self.layer_path(filepath) {
+            if self.layers.contains(&layer) {
+                Self::deep_insert(self.get_root_table_mut(layer), &key, value)
+                    .map_err(|msg| Error::write_error(filepath, &msg))?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Inserts `item` at the dotted `key`. Missing tables are created as implicit tables, so only
+/// the innermost one gets a header. An existing value keeps its surrounding comments, and a
+/// table that replaces a value is written inline in its place.
+fn insert_item(table: &mut dyn toml_edit::TableLike, key: &str, item: Item) -> Result<(), String> {
+    match Config::split_key_once(key) {
+        (key, "") => {
+            let item = match (table.get(key), item) {
+                (Some(Item::Value(_)), Item::Table(t)) => Item::Value(t.into_inline_table().into()),
+                (_, item) => item,
+            };
+            match (table.get_mut(key), item) {
+                (Some(Item::Value(existing)), Item::Value(mut new)) => {
+                    *new.decor_mut() = existing.decor().clone();
+                    *existing = new;
+                }
+                (_, item) => {
+                    table.insert(key, item);
+                }
+            }
+            Ok(())
+        }
+        (key, sub_keys) => {
+            let entry = table.entry(key).or_insert_with(|| {
+                let mut t = Table::new();
+                t.set_implicit(true);
+                Item::Table(t)
+            });
+            match entry.as_table_like_mut() {
+                Some(t) => insert_item(t, sub_keys, item),
+                None => Err(format!("{:?} is not a table, found {}", key, entry)),
+            }
+        }
+    }
+}
+
+fn to_item(value: Value) -> Item {
+    match value {
+        Value::Table(t) => {
+            let mut table = Table::new();
+            for (k, v) in t {
+                table.insert(&k, to_item(v));
+            }
+            Item::Table(table)
+        }
+        v => Item::Value(to_value(v)),
+    }
+}
+
+fn to_value(value: Value) -> toml_edit::Value {
+    match value {
+        Value::String(s) => s.into(),
+        Value::Integer(i) => i.into(),
+        Value::Float(f) => f.into(),
+        Value::Boolean(b) => b.into(),
+        Value::Datetime(d) => d
+            .to_string()
+            .parse::<toml_edit::Datetime>()
+            .expect("toml datetimes are valid toml_edit datetimes")
+            .into(),
+        Value::Array(a) => a
+            .into_iter()
+            .map(to_value)
+            .collect::<toml_edit::Array>()
+            .into(),
+        Value::Table(t) => t
+            .into_iter()
+            .map(|(k, v)| (k, to_value(v)))
+            .collect::<toml_edit::InlineTable>()
+            .into(),
+    }
+}
@@ -0,0 +1,307 @@
+use super::{formats::Format, overrides::override_value, Config, Error, Options};
+use crate::cmd::capture_args;
+use std::{
+    io::Write,
+    path::{Path, PathBuf},
+};
+use toml::{value::Map, Value};
+
+const REMOTE_KEY: &str = "remote";
+
+/// Where a remote layer is fetched from
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Remote {
+    /// All parameters below a path in SSM Parameter Store, i.e. `/team/shared/env/AWS_PROFILE`
+    /// becomes `env.AWS_PROFILE`
+    Ssm(String),
+
+    /// A config file in S3, parsed according to its extension
+    S3(String),
+}
+
+impl TryFrom<&Value> for Remote {
+    type Error = String;
+
+    fn try_from(value: &Value) -> Result<Self, Self::Error> {
+        let table = value.as_table().filter(|t| t.len() == 1);
+        match table.and_then(|t| t.iter().next()) {
+            Some((k, Value::String(path))) if k == "ssm_path" => Ok(Remote::Ssm(path.to_owned())),
+            Some((k, Value::String(url))) if k == "s3" && url.starts_with("s3://") => {
+                Ok(Remote::S3(url.to_owned()))
+            }
+            _ => Err(format!(
+                "expected {{ ssm_path = \"/path/\" }} or {{ s3 = \"s3://bucket/key\" }}, found {}",
+                value
+            )),
+        }
+    }
+}
+
+impl Remote {
+    /// Key of the layer in the config, which is also shown as its source
+    fn layer_path(&self) -> PathBuf {
+        match self {
+            Remote::Ssm(path) => PathBuf::from(format!("ssm:{}", path)),
+            Remote::S3(url) => PathBuf::from(url),
+        }
+    }
+
+    /// Other characters than letters and digits are escaped as `_` and their hex code, so that
+    /// different sources never share a cache file
+    fn cache_path(&self, cache_dir: &Path) -> PathBuf {
+        let name = self
+            .layer_path()
+            .to_string_lossy()
+            .bytes()
+            .map(|b| match b.is_ascii_alphanumeric() {
+                true => (b as char).to_string(),
+                false => format!("_{:02x}", b),
+            })
+            .collect::<String>();
+
+        cache_dir.join("remote").join(format!("{}.toml", name))
+    }
+
+    fn fetch(&self, config: &Config) -> Result<Value, String> {
+        let env = config.get_static_envs();
+
+        match self {
+            Remote::Ssm(path) => {
+                let output = capture_args(
+                    &[
+                        "aws",
+                        "ssm",
+                        "get-parameters-by-path",
+                        "--path",
+                        path,
+                        "--recursive",
+                        "--with-decryption",
+                        "--output",
+                        "json",
+                    ],
+                    &env,
+                    None,
+                )
+                .map_err(|e| e.to_string())?;
+
+                ssm_parameters_to_table(path, &output)
+            }
+            Remote::S3(url) => {
+                let contents = capture_args(&["aws", "s3", "cp", url, "-"], &env, None)
+                    .map_err(|e| e.to_string())?;
+
+                Format::from_path(url)
+                    .parse(&contents, url)
+                    .map_err(|e| e.to_string())
+            }
+        }
+    }
+}
+
+/// Remote layers
+impl Config {
+    /// Fetches the layers declared in the `remote` entries of all loaded files. A remote layer
+    /// ranks right below the file declaring it, later entries of a list rank above earlier
+    /// ones. Fetched layers are cached, the cache is used when fetching fails or `offline` is set.
+    /// As SecureString parameters are cached decrypted, cache files are only readable by their
+    /// owner.
+    pub(crate) fn push_remote_layers(&mut self, options: &Options) -> Result<(), Error> {
+        for filepath in self.layers.clone() {
+            let remotes = match self.file_map.get(&filepath).and_then(|f| f.get(REMOTE_KEY)) {
+                None => continue,
+                Some(Value::Array(a)) => a.iter().collect(),
+                Some(v) => vec![v],
+            }
+            .into_iter()
+            .map(|v| {
+                Remote::try_from(v).map_err(|msg| {
+                    Error::load_error(&filepath, &format!("invalid '{}': {}", REMOTE_KEY, msg))
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+            for remote in remotes {
+                let layer = self.load_remote(&remote, options)?;
+                let position = self
+                    .layers
+                    .iter()
+                    .position(|p| p == &filepath)
+                    .expect("declaring file is a layer");
+
+                self.file_map.insert(remote.layer_path(), layer);
+                self.layers.insert(position + 1, remote.layer_path());
+                self.invalidate_cache();
+            }
+        }
+
+        Ok(())
+    }
+
+    fn load_remote(&self, remote: &Remote, options: &Options) -> Result<Value, Error> {
+        let source = remote.layer_path();
+        let cache_path = options.get_cache_dir().map(|dir| remote.cache_path(&dir));
+
+        let fetch_error = match options.offline {
+            true => "offline".to_owned(),
+            false => match remote.fetch(self) {
+                Ok(layer) => {
+                    if let Some(cache_path) = &cache_path {
+                        write_cache(cache_path, &layer);
+                    }
+                    return Ok(layer);
+                }
+                Err(e) => e,
+            },
+        };
+
+        match cache_path.filter(|p| p.is_file()) {
+            Some(cache_path) => {
+                if !options.offline {
+                    eprintln!(
+                        "warning: could not fetch {:?}, using the cached copy: {}",
+                        source, fetch_error
+                    );
+                }
+                Config::load_one(&cache_path)
+            }
+            None => Err(Error::load_error(
+                &source,
+                &format!(
+                    "could not fetch the remote layer and it is not cached: {}",
+                    fetch_error
+                ),
+            )),
+        }
+    }
+}
+
+/// Turns the output of `aws ssm get-parameters-by-path` into a table. The path of each parameter
+/// below `path` is split at `/` into nested keys, values are parsed like `--set` values, so the
+/// ones below `env` stay strings.
+fn ssm_parameters_to_table(path: &str, output: &str) -> Result<Value, String> {
+    let output = serde_json::from_str::<serde_json::Value>(output).map_err(|e| e.to_string())?;
+    let parameters = output
+        .get("Parameters")
+        .and_then(|p| p.as_array())
+        .ok_or("missing 'Parameters' in the output of aws ssm")?;
+
+    let mut table = Value::Table(Map::new());
+    for parameter in parameters {
+        let (name, value) = match (parameter.get("Name"), parameter.get("Value")) {
+            (Some(serde_json::Value::String(n)), Some(serde_json::Value::String(v))) => (n, v),
+            _ => {
+                return Err(format!(
+                    "invalid parameter in the output of aws ssm: {}",
+                    parameter
+                ))
+            }
+        };
+        let key = name
+            .strip_prefix(path)
+            .unwrap_or(name)
+            .trim_matches('/')
+            .replace('/', ".");
+
+        if let Value::Table(t) = &mut table {
+            Config::deep_insert(t, &key, override_value(&key, value))?;
+        }
+    }
+
+    Ok(table)
+}
+
+/// Remote layers can contain decrypted SecureString parameters, so the cache is only readable
+/// by its owner
+fn write_cache(cache_path: &Path, layer: &Value) {
+    let result = cache_path
+        .parent()
+        .map(std::fs::create_dir_all)
+        .transpose()
+        .map_err(|e| e.to_string())
+        .and_then(|_| toml::to_string(layer).map_err(|e| e.to_string()))
+        .and_then(|s| write_private(cache_path, &s).map_err(|e| e.to_string()));
+
+    if let Err(e) = result {
+        eprintln!("warning: could not cache {:?}: {}", cache_path, e);
+    }
+}
+
+fn write_private(path: &Path, contents: &str) -> std::io::Result<()> {
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+        options.mode(0o600);
+        // the mode only applies to new files, older caches may still be readable by others
+        if path.exists() {
+            std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o600))?;
+        }
+    }
+
+    options.open(path)?.write_all(contents.as_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_remote_try_from() {
+        let remote = |s: &str| Remote::try_from(&Config::parse_value(s));
+
+        assert_eq!(
+            remote(r#"{ ssm_path = "/team/" }"#),
+            Ok(Remote::Ssm("/team/".into()))
+        );
+        assert_eq!(
+            remote(r#"{ s3 = "s3://bucket/a.toml" }"#),
+            Ok(Remote::S3("s3://bucket/a.toml".into()))
+        );
+        assert!(remote(r#"{ s3 = "https://bucket/a.toml" }"#).is_err());
+        assert!(remote(r#"{ ssm_path = "/a/", s3 = "s3://b/c" }"#).is_err());
+    }
+
+    #[test]
+    fn test_ssm_parameters_to_table() {
+        let output = r#"{"Parameters": [
+            {"Name": "/team/shared/env/AWS_DEFAULT_REGION", "Value": "eu-central-1"},
+            {"Name": "/team/shared/env/DEBUG", "Value": "true"},
+            {"Name": "/team/shared/cmd/silent", "Value": "true"}
+        ]}"#;
+
+        let table = ssm_parameters_to_table("/team/shared/", output).unwrap();
+
+        assert_eq!(
+            table.get("env").and_then(|t| t.get("AWS_DEFAULT_REGION")),
+            Some(&Value::String("eu-central-1".into()))
+        );
+        assert_eq!(
+            table.get("env").and_then(|t| t.get("DEBUG")),
+            Some(&Value::String("true".into()))
+        );
+        assert_eq!(
+            table.get("cmd").and_then(|t| t.get("silent")),
+            Some(&Value::Boolean(true))
+        );
+    }
+
+    #[test]
+    fn test_cache_path() {
+        let path = |remote: Remote| remote.cache_path(Path::new("cache"));
+
+        assert_eq!(
+            path(Remote::Ssm("/a-b/".into())),
+            PathBuf::from("cache/remote/ssm_3a_2fa_2db_2f.toml")
+        );
+        assert_ne!(
+            path(Remote::Ssm("/a-b/".into())),
+            path(Remote::Ssm("/a_b/".into()))
+        );
+        assert_ne!(
+            path(Remote::S3("s3://x/a.toml".into())),
+            path(Remote::S3("s3://x/a_toml".into()))
+        );
+    }
+}
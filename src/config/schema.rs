@@ -0,0 +1,241 @@
+use super::{Config, Error};
+use serde_json::{json, Map, Value as JsonValue};
+use toml::Value;
+
+const SCHEMA_KEY: &str = "schema";
+
+/// Schema
+impl Config {
+    /// A JSON Schema describing all keys awsx understands, extended with the declarations in
+    /// the `[schema]` table. Its keys are dotted paths and its values either the name of a
+    /// type, i.e. `"app.port" = "integer"`, or a JSON Schema of their own, i.e.
+    /// `"parameters.InstanceType" = { type = "string", enum = ["t3.micro", "t3.small"] }`.
+    pub fn schema(&self) -> Result<JsonValue, Error> {
+        let mut schema = builtin_schema();
+
+        let declarations = match self.get(SCHEMA_KEY) {
+            None => return Ok(schema),
+            Some(Value::Table(t)) => t,
+            Some(v) => {
+                return Err(Error::InvalidSchema {
+                    key: SCHEMA_KEY.to_owned(),
+                    msg: format!("should be a table, found {}", v),
+                })
+            }
+        };
+
+        for (key, declaration) in declarations {
+            let fragment = match declaration {
+                Value::String(t) => json!({ "type": t }),
+                Value::Table(_) => {
+                    serde_json::to_value(declaration).map_err(|e| Error::InvalidSchema {
+                        key: key.to_owned(),
+                        msg: e.to_string(),
+                    })?
+                }
+                v => {
+                    return Err(Error::InvalidSchema {
+                        key: key.to_owned(),
+                        msg: format!("should be a type name or a table, found {}", v),
+                    })
+                }
+            };
+
+            declare(&mut schema, key, fragment);
+        }
+
+        Ok(schema)
+    }
+}
+
+/// Adds `fragment` to the schema of the dotted `key`, creating the schemas of all parent
+/// objects on the way. Fields of an existing schema that `fragment` does not set are kept.
+fn declare(schema: &mut JsonValue, key: &str, fragment: JsonValue) {
+    let parts = key.split('.').collect::<Vec<_>>();
+
+    let target = parts.iter().enumerate().fold(schema, |schema, (i, part)| {
+        schema
+            .as_object_mut()
+            .expect("schemas are objects")
+            .entry("properties")
+            .or_insert_with(|| JsonValue::Object(Map::new()))
+            .as_object_mut()
+            .expect("properties are objects")
+            .entry(*part)
+            .or_insert_with(|| match i == parts.len() - 1 {
+                true => json!({}),
+                false => json!({ "type": "object" }),
+            })
+    });
+
+    match (target, fragment) {
+        (JsonValue::Object(target), JsonValue::Object(fragment)) => target.extend(fragment),
+        (target, fragment) => *target = fragment,
+    }
+}
+
+/// The keys that awsx reads itself. All other keys are allowed as well.
+fn builtin_schema() -> JsonValue {
+    let strings = json!({ "type": "array", "items": { "type": "string" } });
+    let encrypted = json!({
+        "type": "object",
+        "description": "A value that is decrypted when it is read, written by `awsx config encrypt`",
+        "properties": {
+            "encrypted": { "type": "string", "description": "The base64 encoded ciphertext" },
+            "kms": { "type": "string", "description": "The KMS key that encrypted the value, age is used without it" }
+        },
+        "required": ["encrypted"],
+        "additionalProperties": false
+    });
+    let remote = json!({
+        "oneOf": [
+            {
+                "type": "object",
+                "properties": { "ssm_path": { "type": "string" } },
+                "required": ["ssm_path"],
+                "additionalProperties": false
+            },
+            {
+                "type": "object",
+                "properties": { "s3": { "type": "string", "pattern": "^s3://" } },
+                "required": ["s3"],
+                "additionalProperties": false
+            }
+        ]
+    });
+    let scalar = json!({
+        "type": ["string", "integer", "number", "boolean"]
+    });
+    let list = json!({
+        "type": "array",
+        "description": "Items of a List<...> or CommaDelimitedList parameter",
+        "items": scalar
+    });
+    let array_merge = json!({
+        "oneOf": [
+            { "enum": ["replace", "append"] },
+            {
+                "type": "object",
+                "properties": { "by_key": { "type": "string" } },
+                "required": ["by_key"],
+                "additionalProperties": false
+            }
+        ]
+    });
+
+    json!({
+        "$schema": "http://json-schema.org/draft-07/schema#",
+        "title": "awsx config",
+        "type": "object",
+        "properties": {
+            "include": {
+                "description": "Config files that are loaded with a lower precedence than this one",
+                "oneOf": [{ "type": "string" }, strings]
+            },
+            "remote": {
+                "description": "Layers fetched from SSM Parameter Store or S3 that rank below this file",
+                "oneOf": [remote, { "type": "array", "items": remote }]
+            },
+            "env_file": {
+                "description": "Dotenv files that are imported into the env table",
+                "oneOf": [{ "type": "string" }, strings]
+            },
+            "env": {
+                "type": "object",
+                "description": "Environment variables for all commands, `{{ }}` values are evaluated as expressions",
+                "properties": {
+                    "AWS_PROFILE": { "type": "string", "description": "Profile used for all aws calls" },
+                    "AWS_DEFAULT_REGION": { "type": "string", "description": "Region used for all aws calls" }
+                },
+                "additionalProperties": { "oneOf": [{ "type": "string" }, encrypted] }
+            },
+            "parameters": {
+                "type": "object",
+                "description": "Values for the parameters of CloudFormation templates",
+                "additionalProperties": {
+                    "oneOf": [
+                        scalar,
+                        list,
+                        encrypted,
+                        {
+                            "type": "object",
+                            "properties": {
+                                "value": { "oneOf": [scalar, list, encrypted] },
+                                "expose": {
+                                    "type": "boolean",
+                                    "description": "Also export the value as AWSX_PARAMETER_<NAME>"
+                                }
+                            },
+                            "required": ["value"],
+                            "additionalProperties": false
+                        }
+                    ]
+                }
+            },
+            "cmd": {
+                "type": "object",
+                "properties": {
+                    "silent": { "type": "boolean", "description": "Hide the output of all commands" }
+                }
+            },
+            "merge": {
+                "type": "object",
+                "properties": {
+                    "arrays": {
+                        "type": "object",
+                        "description": "How arrays of different layers are combined, by dotted key or `default`",
+                        "additionalProperties": array_merge
+                    }
+                }
+            },
+            "environments": {
+                "type": "object",
+                "description": "Tables that are applied on top of all config files with --env",
+                "additionalProperties": {
+                    "allOf": [
+                        { "$ref": "#" },
+                        {
+                            "properties": {
+                                "extends": { "type": "string", "description": "Environment this one is based on" }
+                            }
+                        }
+                    ]
+                }
+            },
+            "expressions": {
+                "type": "object",
+                "properties": {
+                    "interpreter": { "enum": ["bash", "sh", "python3", "none"] },
+                    "timeout": { "type": "number", "exclusiveMinimum": 0, "description": "In seconds" },
+                    "env": {
+                        "description": "Only these environment variables are passed to expressions",
+                        "type": "array",
+                        "items": { "type": "string" }
+                    },
+                    "allowed_commands": {
+                        "description": "Only these commands may be called by expressions",
+                        "type": "array",
+                        "items": { "type": "string" }
+                    }
+                }
+            },
+            "secrets": {
+                "type": "object",
+                "properties": {
+                    "identity": { "type": "string", "description": "Path of the age identity file" },
+                    "recipients": {
+                        "description": "Age public keys that values are encrypted for",
+                        "type": "array",
+                        "items": { "type": "string" }
+                    }
+                }
+            },
+            "schema": {
+                "type": "object",
+                "description": "Schemas of your own keys, by dotted key",
+                "additionalProperties": { "oneOf": [{ "type": "string" }, { "type": "object" }] }
+            }
+        },
+        "additionalProperties": true
+    })
+}
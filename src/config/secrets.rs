@@ -0,0 +1,221 @@
+use super::{includes::home_dir, Config, Error};
+use crate::cmd::capture_args;
+use age::{armor::ArmoredReader, x25519, Decryptor, Encryptor, IdentityFile, Recipient};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use std::{
+    io::{Read, Write},
+    path::{Path, PathBuf},
+};
+use toml::{value::Map, Value};
+
+const ENCRYPTED_KEY: &str = "encrypted";
+const KMS_KEY: &str = "kms";
+const IDENTITY_KEY: &str = "secrets.identity";
+const RECIPIENTS_KEY: &str = "secrets.recipients";
+const DEFAULT_IDENTITY: &str = "~/.config/awsx/age.key";
+const ARMOR_BEGIN: &str = "-----BEGIN AGE ENCRYPTED FILE-----";
+
+/// Returns the ciphertext and the KMS key id, if there is one, if `value` is a table of the
+/// form `{ encrypted = "..." }` or `{ encrypted = "...", kms = "<key id>" }`
+pub(super) fn as_encrypted(value: &Value) -> Option<(&str, Option<&str>)> {
+    let table = value.as_table()?;
+    let ciphertext = table.get(ENCRYPTED_KEY)?.as_str()?;
+
+    match (table.get(KMS_KEY), table.len()) {
+        (None, 1) => Some((ciphertext, None)),
+        (Some(Value::String(kms)), 2) => Some((ciphertext, Some(kms))),
+        _ => None,
+    }
+}
+
+/// Secrets
+impl Config {
+    /// Returns the plaintext of `value` if it is encrypted and `None` otherwise. Age ciphertexts
+    /// are decrypted with the identity file at `secrets.identity`, which defaults to
+    /// `~/.config/awsx/age.key`, and KMS ciphertexts with `aws kms decrypt`.
+    pub fn decrypt(&self, key: impl AsRef<str>, value: &Value) -> Result<Option<String>, Error> {
+        let error = |msg: String| Error::Decrypt {
+            key: key.as_ref().to_owned(),
+            msg,
+        };
+
+        let plaintext = match as_encrypted(value) {
+            None => return Ok(None),
+            Some((ciphertext, None)) => self.age_decrypt(ciphertext).map_err(error)?,
+            Some((ciphertext, Some(kms))) => self.kms_decrypt(ciphertext, kms).map_err(error)?,
+        };
+
+        String::from_utf8(plaintext)
+            .map(Some)
+            .map_err(|_| error("the plaintext is not valid utf-8".to_owned()))
+    }
+
+    /// Encrypts `plaintext` with the given KMS key, or for the age recipients listed in
+    /// `secrets.recipients` if no key is given. Without recipients, the public keys of the
+    /// identity file are used. Returns the table that replaces the plaintext in a config file.
+    pub fn encrypt(
+        &self,
+        key: impl AsRef<str>,
+        plaintext: &str,
+        kms: Option<&str>,
+    ) -> Result<Value, Error> {
+        let error = |msg: String| Error::Encrypt {
+            key: key.as_ref().to_owned(),
+            msg,
+        };
+
+        let mut table = Map::new();
+        match kms {
+            Some(kms) => {
+                let ciphertext = self.kms_encrypt(plaintext, kms).map_err(error)?;
+                table.insert(ENCRYPTED_KEY.to_owned(), Value::String(ciphertext));
+                table.insert(KMS_KEY.to_owned(), Value::String(kms.to_owned()));
+            }
+            None => {
+                let ciphertext = self.age_encrypt(plaintext).map_err(error)?;
+                table.insert(ENCRYPTED_KEY.to_owned(), Value::String(ciphertext));
+            }
+        }
+
+        Ok(Value::Table(table))
+    }
+
+    fn age_decrypt(&self, ciphertext: &str) -> Result<Vec<u8>, String> {
+        let ciphertext = match ciphertext.trim_start().starts_with(ARMOR_BEGIN) {
+            true => ciphertext.as_bytes().to_vec(),
+            false => STANDARD
+                .decode(ciphertext.trim())
+                .map_err(|e| format!("invalid base64: {}", e))?,
+        };
+
+        let identities = self
+            .identity_file()?
+            .into_identities()
+            .map_err(|e| e.to_string())?;
+
+        let decryptor = Decryptor::new_buffered(ArmoredReader::new(ciphertext.as_slice()))
+            .map_err(|e| e.to_string())?;
+
+        let mut plaintext = Vec::new();
+        decryptor
+            .decrypt(identities.iter().map(|i| i.as_ref() as _))
+            .map_err(|e| e.to_string())?
+            .read_to_end(&mut plaintext)
+            .map_err(|e| e.to_string())?;
+
+        Ok(plaintext)
+    }
+
+    fn age_encrypt(&self, plaintext: &str) -> Result<String, String> {
+        let recipients: Vec<Box<dyn Recipient + Send>> = match self.get(RECIPIENTS_KEY) {
+            Some(Value::Array(a)) => a
+                .iter()
+                .map(|v| {
+                    v.as_str()
+                        .and_then(|s| s.parse::<x25519::Recipient>().ok())
+                        .map(|r| Box::new(r) as _)
+                        .ok_or_else(|| {
+                            format!("invalid age recipient in {}: {}", RECIPIENTS_KEY, v)
+                        })
+                })
+                .collect::<Result<_, _>>()?,
+            Some(v) => return Err(format!("{} should be a list, found {}", RECIPIENTS_KEY, v)),
+            None => self
+                .identity_file()?
+                .to_recipients()
+                .map_err(|e| e.to_string())?,
+        };
+
+        let encryptor = Encryptor::with_recipients(recipients.iter().map(|r| r.as_ref() as _))
+            .map_err(|e| e.to_string())?;
+
+        let mut ciphertext = Vec::new();
+        let mut writer = encryptor
+            .wrap_output(&mut ciphertext)
+            .map_err(|e| e.to_string())?;
+        writer
+            .write_all(plaintext.as_bytes())
+            .and_then(|_| writer.finish())
+            .map_err(|e| e.to_string())?;
+
+        Ok(STANDARD.encode(ciphertext))
+    }
+
+    fn kms_decrypt(&self, ciphertext: &str, kms: &str) -> Result<Vec<u8>, String> {
+        let plaintext = capture_args(
+            &[
+                "aws",
+                "kms",
+                "decrypt",
+                "--key-id",
+                kms,
+                "--ciphertext-blob",
+                ciphertext,
+                "--query",
+                "Plaintext",
+                "--output",
+                "text",
+            ],
+            &self.get_static_envs(),
+            None,
+        )
+        .map_err(|e| format!("aws kms decrypt: {}", e))?;
+
+        STANDARD
+            .decode(plaintext.trim())
+            .map_err(|e| format!("invalid base64 from aws kms decrypt: {}", e))
+    }
+
+    /// The plaintext is passed on stdin, so that it does not show up in the process list
+    fn kms_encrypt(&self, plaintext: &str, kms: &str) -> Result<String, String> {
+        capture_args(
+            &[
+                "aws",
+                "kms",
+                "encrypt",
+                "--key-id",
+                kms,
+                "--plaintext",
+                "fileb:///dev/stdin",
+                "--query",
+                "CiphertextBlob",
+                "--output",
+                "text",
+            ],
+            &self.get_static_envs(),
+            Some(plaintext.as_bytes()),
+        )
+        .map(|ciphertext| ciphertext.trim().to_owned())
+        .map_err(|e| format!("aws kms encrypt: {}", e))
+    }
+
+    fn identity_file(&self) -> Result<IdentityFile<age::NoCallbacks>, String> {
+        let path = self.identity_path().map_err(|e| e.to_string())?;
+
+        IdentityFile::from_file(path.to_string_lossy().to_string())
+            .map_err(|e| format!("could not read the age identity file {:?}: {}", path, e))
+    }
+
+    /// Relative paths in `secrets.identity` are resolved from the directory of the file that
+    /// sets it, a leading `~` is replaced with the home directory
+    fn identity_path(&self) -> Result<PathBuf, Error> {
+        let (path, filepath) = match self.get_with_filepath(IDENTITY_KEY) {
+            Some((Value::String(s), filepath)) => (s.to_owned(), filepath),
+            Some((v, filepath)) => {
+                return Err(Error::load_error(
+                    filepath,
+                    &format!("{} should be a path, found {}", IDENTITY_KEY, v),
+                ))
+            }
+            None => (DEFAULT_IDENTITY.to_owned(), PathBuf::new()),
+        };
+
+        Ok(match path.strip_prefix("~/") {
+            Some(rest) => home_dir(&filepath)?.join(rest),
+            None => filepath
+                .parent()
+                .unwrap_or_else(|| Path::new(""))
+                .join(path),
+        })
+    }
+}
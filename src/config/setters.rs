@@ -6,6 +6,8 @@ use toml::{value::Map, Value};
 /// Setters
 impl Config {
     pub fn set(&mut self, key: impl AsRef<str>, value: Value) {
+        self.override_sources.remove(key.as_ref());
+
         let keys = key.as_ref().split('.').collect::<Vec<_>>();
         let last_key = keys.last().expect("at least one entry").to_string();
 
@@ -30,6 +32,37 @@ impl Config {
         }
     }
 
+    /// Parses `raw` as a TOML value, i.e. `42`, `true` or `["a", "b"]`.
+    /// Anything that is not a valid TOML value is treated as a plain string.
+    pub fn parse_value(raw: impl AsRef<str>) -> Value {
+        format!("value = {}", raw.as_ref())
+            .parse::<Value>()
+            .ok()
+            .and_then(|mut v| v.as_table_mut()?.remove("value"))
+            .unwrap_or_else(|| Value::String(raw.as_ref().to_owned()))
+    }
+
+    /// Inserts `value` at the dotted `key`, creating missing tables along the way
+    pub(crate) fn deep_insert(
+        table: &mut Map<String, Value>,
+        key: impl AsRef<str>,
+        value: Value,
+    ) -> Result<(), String> {
+        match Self::split_key_once(key.as_ref()) {
+            (key, "") => {
+                table.insert(key.to_owned(), value);
+                Ok(())
+            }
+            (key, sub_keys) => match table
+                .entry(key.to_owned())
+                .or_insert_with(|| Value::Table(Map::new()))
+            {
+                Value::Table(t) => Self::deep_insert(t, sub_keys, value),
+                v => Err(format!("{:?} is not a table, found {}", key, v)),
+            },
+        }
+    }
+
     fn nested_insert(table: &mut Map<String, Value>, key: impl AsRef<str>, value: Value) {
         let mut keys = key.as_ref().split('.').collect::<Vec<_>>();
         let first_key = keys.first().expect("at least one entry").to_string();
@@ -72,14 +105,17 @@ impl Config {
                 ),
                 prefix,
             ),
+            // a key below a value replaces it, just like setting the value again would
             Some(v) => {
-                todo!(
-                    "handle invalid nested key\nfound a value but not a table, continuing...\n{:?}",
-                    v
-                );
+                *v = Value::Table(Map::new());
+                (
+                    self.get_from_file_mut(&prefix, PathBuf::from(OVERRIDE_FILEPATH))
+                        .and_then(Value::as_table_mut),
+                    prefix,
+                )
             }
-            _ if keys.len() > 2 => self.find_first_existing_table(prefix),
-            _ => (None, "".to_string()),
+            None if keys.len() > 2 => self.find_first_existing_table(prefix),
+            None => (None, "".to_string()),
         }
     }
 }
@@ -0,0 +1,71 @@
+use super::{Config, Error};
+use serde::de::DeserializeOwned;
+use std::path::PathBuf;
+use toml::Value;
+
+/// Typed getters
+impl Config {
+    /// Deserializes the value of `key` as defined by the layer with the highest precedence,
+    /// ignoring everything lower layers define for it
+    pub fn get_as<T: DeserializeOwned>(&self, key: impl AsRef<str>) -> Result<T, Error> {
+        let (value, filepath) = self
+            .sorted_filepaths()
+            .into_iter()
+            .find_map(|filepath| {
+                self.get_from_file(&key, &filepath)
+                    .map(|v| (v.to_owned(), filepath))
+            })
+            .ok_or_else(|| Error::MissingKey {
+                key: key.as_ref().to_owned(),
+            })?;
+
+        deserialize(value, key.as_ref(), |_| Some(filepath.clone()))
+    }
+
+    /// Deserializes the deep merge of `key` across all layers
+    pub fn merged_as<T: DeserializeOwned>(&self, key: impl AsRef<str>) -> Result<T, Error> {
+        let value = self.get(&key).ok_or_else(|| Error::MissingKey {
+            key: key.as_ref().to_owned(),
+        })?;
+
+        deserialize(value.to_owned(), key.as_ref(), |k| {
+            self.get_with_filepath(k).map(|(_, filepath)| filepath)
+        })
+    }
+}
+
+/// Deserializes `value` found at `key`. On failure the error names the full key of the
+/// offending value and the file it was taken from, as reported by `source`.
+fn deserialize<T: DeserializeOwned>(
+    value: Value,
+    key: &str,
+    source: impl Fn(&str) -> Option<PathBuf>,
+) -> Result<T, Error> {
+    serde_path_to_error::deserialize(value).map_err(|e| {
+        let path = e.path().to_string();
+        let full_key = match path.as_str() {
+            "." => key.to_owned(),
+            path => format!("{}.{}", key, path),
+        };
+
+        // missing fields and list entries have no source themselves, so use their parent's
+        let mut lookup = full_key.split('[').next().unwrap_or(&full_key).to_owned();
+        let filepath = loop {
+            if let Some(filepath) = source(&lookup) {
+                break Some(filepath);
+            }
+            match lookup.rsplit_once('.') {
+                Some((parent, _)) => lookup = parent.to_owned(),
+                None => break None,
+            }
+        };
+
+        Error::Deserialize {
+            key: full_key,
+            path: filepath
+                .map(|p| p.to_string_lossy().to_string())
+                .unwrap_or_default(),
+            msg: e.inner().to_string(),
+        }
+    })
+}
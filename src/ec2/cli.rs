@@ -163,6 +163,10 @@ pub fn stop_instance(instance_id: String, config: &Config) -> Result<(), anyhow:
 pub fn get_latest_ami(filter: Option<String>, with_name: bool, config: &Config) -> Result<()> {
     let cmd = "aws ec2 describe-images --owners self --query \"Images[].[CreationDate, Name, ImageId] | sort_by(@, &[0])\" --output text";
     let out = read(cmd, config)?;
+    let envs = match &filter {
+        Some(filter) if filter.starts_with('$') => config.try_get_envs()?,
+        _ => Default::default(),
+    };
     let latest_ami = out
         .lines()
         .filter_map(|line| {
@@ -177,7 +181,6 @@ pub fn get_latest_ami(filter: Option<String>, with_name: bool, config: &Config)
             match &filter {
                 Some(filter) => {
                     if let Some(filter) = filter.strip_prefix('$') {
-                        let envs = config.get_envs();
                         let var = envs.get(filter)?;
                         name.contains(var).then_some(out)
                     } else {
@@ -187,7 +190,7 @@ pub fn get_latest_ami(filter: Option<String>, with_name: bool, config: &Config)
                 None => Some(out),
             }
         })
-        .last()
+        .next_back()
         .ok_or(anyhow::anyhow!("No AMI found"))?;
 
     println!("{}", latest_ami);
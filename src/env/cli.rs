@@ -5,7 +5,7 @@ use std::{collections::HashMap, path::PathBuf};
 pub fn substitute_env_vars(file: PathBuf, _output: Option<PathBuf>, config: &Config) -> Result<()> {
     let mut filestring = std::fs::read_to_string(file)?;
     let env_vars = config
-        .get_envs()
+        .try_get_envs()?
         .into_iter()
         .chain(std::env::vars())
         .collect::<HashMap<_, _>>();
@@ -29,7 +29,7 @@ pub fn substitute_env_vars(file: PathBuf, _output: Option<PathBuf>, config: &Con
 }
 
 pub fn print_env_vars(config: &crate::config::Config) -> Result<()> {
-    let config_envs = config.get_envs();
+    let config_envs = config.try_get_envs()?;
     let keys: Vec<_> = config_envs.clone().into_keys().collect();
 
     config_envs
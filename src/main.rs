@@ -1,18 +1,48 @@
-use awsx::config::Config;
+use awsx::config::{Config, Options};
 use clap::Parser;
 use std::path::PathBuf;
 
 #[derive(Debug, clap::Parser)]
 #[clap(name = "awsx", about = "Opinionated wrapper around the AWS CLI")]
 pub struct Args {
-    /// Path to 'config.toml' file. Will scan every file with the same name up to the project root.
-    #[clap(long, short = 'c')]
-    config: PathBuf,
-
-    /// The default value is the first parent folder containing a .git folder.
+    /// Path to a config file or a directory containing config files. Can be used multiple times,
+    /// later files take precedence. Will also scan every directory up to the project root for
+    /// 'config.toml', 'config.yaml' and 'config.json' files.
+    #[clap(long, short = 'c', required = true)]
+    config: Vec<PathBuf>,
+
+    /// Directory where the scan for config files stops. The default value is the closest parent
+    /// folder containing a '.awsx-root' marker or '.git'.
     #[clap(long, short = 'p')]
     project_root: Option<PathBuf>,
 
+    /// Name of the config files to scan for instead of 'config.toml', 'config.yaml' and
+    /// 'config.json'. Can be used multiple times, earlier names take precedence.
+    #[clap(long, value_name = "NAME")]
+    config_name: Vec<String>,
+
+    /// Only load the given config file instead of scanning every directory up to the project root.
+    #[clap(long, action)]
+    no_nested: bool,
+
+    /// Name of an `[environments.<name>]` table to apply on top of the config files.
+    #[clap(long, short = 'e', env = "AWSX_ENV")]
+    env: Option<String>,
+
+    /// Use the cached copies of remote config layers instead of fetching them.
+    #[clap(long, action)]
+    offline: bool,
+
+    /// Evaluate all config expressions instead of using the values pinned in 'awsx.lock'.
+    #[clap(long, action)]
+    refresh: bool,
+
+    /// Overrides a config value, i.e. `--set parameters.ImageTag=v1.2.3`. Values are parsed as
+    /// TOML and used as plain strings if that fails. Can be used multiple times.
+    /// Precedence: `--set` > `AWSX__SECTION__KEY` env vars > `--env` > config files.
+    #[clap(long = "set", value_name = "KEY=VALUE")]
+    set: Vec<String>,
+
     /// Just print the command(s) that would run instead of actually running them.
     #[clap(long, short = 'n')]
     dry_run: Option<PathBuf>,
@@ -23,6 +53,9 @@ pub struct Args {
 
 #[derive(Debug, clap::Subcommand)]
 pub enum Subcommands {
+    #[clap(subcommand)]
+    Config(awsx::config::Subcommands),
+
     #[clap(subcommand)]
     Env(awsx::env::Subcommands),
 
@@ -43,15 +76,56 @@ pub enum Subcommands {
 
     #[clap(subcommand)]
     Secrets(awsx::secrets::Subcommands),
+
+    /// Evaluates all config expressions and pins their results in an 'awsx.lock' file next to
+    /// the config, which is used instead of evaluating them again until '--refresh' is passed.
+    Lock {},
 }
 
 #[cfg(not(tarpaulin_include))]
 fn main() -> anyhow::Result<()> {
     let args = Args::parse();
 
-    let config = Config::from_path(args.config, Default::default())?;
+    let mut options = Options {
+        nested: !args.no_nested,
+        project_root: args.project_root,
+        environment: args.env,
+        offline: args.offline,
+        refresh: args.refresh,
+        ..Default::default()
+    };
+    if !args.config_name.is_empty() {
+        options.filenames = args.config_name;
+    }
+    let mut config = Config::from_paths(args.config, options.clone())?;
+    config.set_from_env_vars(std::env::vars())?;
+    config.set_from_args(&args.set)?;
 
     match args.cmd {
+        Subcommands::Config(cmd) => match cmd {
+            awsx::config::Subcommands::Get { key, show_source } => {
+                awsx::config::get(key, show_source, &config)
+            }
+            awsx::config::Subcommands::List {} => awsx::config::list(&config),
+            awsx::config::Subcommands::Explain { key } => awsx::config::explain(key, &config),
+            awsx::config::Subcommands::Set { key, value, file } => {
+                awsx::config::set(key, value, file, &mut config)
+            }
+            awsx::config::Subcommands::Diff {
+                a,
+                b,
+                resolve,
+                decrypt,
+            } => awsx::config::diff(a, b, resolve, decrypt, options),
+            awsx::config::Subcommands::Schema {} => awsx::config::schema(&config),
+            awsx::config::Subcommands::Encrypt {
+                key,
+                value,
+                kms,
+                file,
+            } => awsx::config::encrypt(key, value, kms, file, &mut config),
+        },
+
         Subcommands::Env(cmd) => match cmd {
             awsx::env::Subcommands::Substitute { file, output } => {
                 awsx::env::substitute_env_vars(file, output, &config)
@@ -68,6 +142,19 @@ fn main() -> anyhow::Result<()> {
                 stack_name,
                 template,
             } => awsx::stack::update(stack_name, template, &config),
+            awsx::stack::Subcommands::Deploy {
+                stack_name,
+                template,
+                yes,
+            } => awsx::stack::deploy(stack_name, template, yes, &config),
+            awsx::stack::Subcommands::Plan {
+                stack_name,
+                template,
+            } => awsx::stack::plan(stack_name, template, &config),
+            awsx::stack::Subcommands::Apply {
+                stack_name,
+                change_set,
+            } => awsx::stack::apply(stack_name, change_set, &config),
             awsx::stack::Subcommands::Destroy { stack_name } => {
                 awsx::stack::destroy(stack_name, &config)
             }
@@ -137,6 +224,8 @@ fn main() -> anyhow::Result<()> {
         Subcommands::Secrets(cmd) => match cmd {
             awsx::secrets::Subcommands::Get { name, key } => awsx::secrets::get(name, key, &config),
         },
+
+        Subcommands::Lock {} => awsx::config::lock(&mut config),
     }?;
 
     Ok(())
@@ -1,36 +1,39 @@
-use super::util::{get_parameter_values_from_config, parameters_to_string};
+use super::util::{
+    deploy_action, format_parameter_value, get_parameters_from_config, operation_succeeded,
+    parameters_to_string, render_change_set, root_causes, DeployAction, StackEvent,
+};
+use super::Template;
 use crate::{
-    cmd::{read, run},
+    cmd::{capture, read, read_output, run},
     config::Config,
 };
 use anyhow::Result;
-use std::path::Path;
+use std::{
+    collections::HashSet,
+    io::{BufRead, IsTerminal, Write},
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+const POLL_INTERVAL: Duration = Duration::from_secs(5);
 
 pub fn create(
     stack_name: impl AsRef<str>,
     template: impl AsRef<Path>,
     config: &Config,
 ) -> Result<()> {
-    // TODO: Can we deduplicate some code here regarding the expression evaluation in the config parameters?
-    // This work is already being done inside the `run` function
-    let parameters = parameters_to_string(get_parameter_values_from_config(&template, config)?);
+    let (parameters, sensitive) = template_parameters(&template, config)?;
 
     let cmd = format!(
             "aws cloudformation create-stack --stack-name {} --template-body file://{} --capabilities CAPABILITY_NAMED_IAM {}",
             stack_name.as_ref(), template.as_ref().to_string_lossy(), parameters
         );
-    run(&cmd, config)?;
+    let stack_id = capture(&format!("{} --output text --query StackId", cmd), config)
+        .map_err(|e| redact(e, &sensitive))?;
 
     println!("Creating stack: {:?}", stack_name.as_ref());
-    println!("Waiting for completion...");
 
-    run(
-        &format!(
-            "aws cloudformation wait stack-create-complete --stack-name {}",
-            stack_name.as_ref()
-        ),
-        config,
-    )?;
+    watch(stack_id.trim(), None, config)?;
 
     println!("Done");
 
@@ -42,52 +45,463 @@ pub fn update(
     template: impl AsRef<Path>,
     config: &Config,
 ) -> Result<()> {
-    // TODO: Can we deduplicate some code here regarding the expression evaluation in the config parameters?
-    // This work is already being done inside the `run` function
-    let parameters = parameters_to_string(get_parameter_values_from_config(&template, config)?);
+    let (parameters, sensitive) = template_parameters(&template, config)?;
 
     let cmd = format!(
             "aws cloudformation update-stack --stack-name {} --template-body file://{} --capabilities CAPABILITY_NAMED_IAM {}",
             stack_name.as_ref(), template.as_ref().to_string_lossy(), parameters
         );
-    run(&cmd, config)?;
+    let stack_id = stack_id(&stack_name, config)?
+        .ok_or_else(|| anyhow::anyhow!("Stack {:?} does not exist", stack_name.as_ref()))?;
+    let since = latest_event(&stack_id, config)?;
+
+    let output = read_output(&cmd, config)?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        if stderr.contains("No updates are to be performed") {
+            println!("Stack {:?} is up to date", stack_name.as_ref());
+            return Ok(());
+        }
+        return Err(redact(
+            anyhow::anyhow!(
+                "Could not update stack {:?}: {}",
+                stack_name.as_ref(),
+                stderr.trim()
+            ),
+            &sensitive,
+        ));
+    }
 
     println!("Updating stack: {:?}", stack_name.as_ref());
+
+    watch(&stack_id, since, config)?;
+
+    println!("Done");
+
+    Ok(())
+}
+
+/// Creates the stack if it does not exist and updates it otherwise. A stack that failed to be
+/// created is deleted and created again, after asking for confirmation unless `yes` is set.
+pub fn deploy(
+    stack_name: impl AsRef<str>,
+    template: impl AsRef<Path>,
+    yes: bool,
+    config: &Config,
+) -> Result<()> {
+    match deploy_action(stack_status(&stack_name, config)?.as_deref()) {
+        DeployAction::Create => create(stack_name, template, config),
+        DeployAction::Update => update(stack_name, template, config),
+        DeployAction::Recreate => {
+            let question = format!(
+                "Stack {:?} failed to be created and can not be updated. Delete and create it again?",
+                stack_name.as_ref()
+            );
+            if !yes && !confirm(&question)? {
+                anyhow::bail!(
+                    "Stack {:?} is in ROLLBACK_COMPLETE, it has to be deleted before it can be deployed",
+                    stack_name.as_ref()
+                );
+            }
+            destroy(&stack_name, config)?;
+            create(stack_name, template, config)
+        }
+        DeployAction::Review => anyhow::bail!(
+            "Stack {:?} was planned but never created, run `awsx stack apply {}` or `awsx stack destroy {}` first",
+            stack_name.as_ref(),
+            stack_name.as_ref(),
+            stack_name.as_ref()
+        ),
+        DeployAction::Busy(status) => anyhow::bail!(
+            "Stack {:?} is busy ({}), try again when it is done",
+            stack_name.as_ref(),
+            status
+        ),
+        DeployAction::Blocked(status) => anyhow::bail!(
+            "Stack {:?} is in {} and needs to be fixed before it can be deployed",
+            stack_name.as_ref(),
+            status
+        ),
+    }
+}
+
+/// Creates a change set for the stack and prints the changes it would make. Its name is saved
+/// so that `apply` can execute it later. A previous plan is discarded first, so that `apply`
+/// never executes a stale change set when the new one has no changes or can not be created.
+pub fn plan(
+    stack_name: impl AsRef<str>,
+    template: impl AsRef<Path>,
+    config: &Config,
+) -> Result<()> {
+    let change_set_type = match deploy_action(stack_status(&stack_name, config)?.as_deref()) {
+        // a stack that is still in review can get another CREATE change set
+        DeployAction::Create | DeployAction::Review => "CREATE",
+        DeployAction::Update => "UPDATE",
+        DeployAction::Recreate => anyhow::bail!(
+            "Stack {:?} is in ROLLBACK_COMPLETE, it has to be deleted before it can be planned",
+            stack_name.as_ref()
+        ),
+        DeployAction::Busy(status) | DeployAction::Blocked(status) => anyhow::bail!(
+            "Stack {:?} is in {} and can not be planned",
+            stack_name.as_ref(),
+            status
+        ),
+    };
+
+    let plan_path = plan_path(&stack_name, config);
+    if plan_path.is_file() {
+        std::fs::remove_file(&plan_path)?;
+    }
+
+    let (parameters, sensitive) = template_parameters(&template, config)?;
+    let change_set_name = format!(
+        "awsx-{}",
+        SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs()
+    );
+
+    let cmd = format!(
+            "aws cloudformation create-change-set --stack-name {} --change-set-name {} --change-set-type {} --template-body file://{} --capabilities CAPABILITY_NAMED_IAM {}",
+            stack_name.as_ref(), change_set_name, change_set_type, template.as_ref().to_string_lossy(), parameters
+        );
+    read(&cmd, config).map_err(|e| redact(e, &sensitive))?;
+
+    println!("Creating change set: {:?}", change_set_name);
     println!("Waiting for completion...");
 
+    // the waiter fails for change sets without changes, describe-change-set tells why
+    read_output(
+        &format!(
+            "aws cloudformation wait change-set-create-complete --stack-name {} --change-set-name {}",
+            stack_name.as_ref(),
+            change_set_name
+        ),
+        config,
+    )?;
+
+    let change_set: serde_json::Value = serde_json::from_str(&capture(
+        &format!(
+            "aws cloudformation describe-change-set --stack-name {} --change-set-name {} --output json",
+            stack_name.as_ref(),
+            change_set_name
+        ),
+        config,
+    )?)?;
+
+    let status = change_set["Status"].as_str().unwrap_or_default();
+    let reason = change_set["StatusReason"].as_str().unwrap_or_default();
+
+    if status == "FAILED" {
+        if reason.contains("didn't contain changes") || reason.contains("No updates") {
+            read(
+                &format!(
+                    "aws cloudformation delete-change-set --stack-name {} --change-set-name {}",
+                    stack_name.as_ref(),
+                    change_set_name
+                ),
+                config,
+            )?;
+            println!("No changes, stack {:?} is up to date", stack_name.as_ref());
+            return Ok(());
+        }
+        anyhow::bail!(
+            "Could not create change set for stack {:?}: {}",
+            stack_name.as_ref(),
+            reason
+        );
+    }
+
+    println!("{}", render_change_set(&change_set));
+
+    save_plan(&plan_path, &change_set_name)?;
+
+    println!(
+        "Run `awsx stack apply {}` to execute the change set",
+        stack_name.as_ref()
+    );
+
+    Ok(())
+}
+
+/// Executes the change set saved by `plan`, or the given one, and waits for it to complete
+pub fn apply(
+    stack_name: impl AsRef<str>,
+    change_set: Option<impl AsRef<str>>,
+    config: &Config,
+) -> Result<()> {
+    let plan_path = plan_path(&stack_name, config);
+    let change_set_name = match change_set {
+        Some(name) => name.as_ref().to_owned(),
+        None => std::fs::read_to_string(&plan_path)
+            .map(|name| name.trim().to_owned())
+            .map_err(|_| {
+                anyhow::anyhow!(
+                    "No plan for stack {:?}, run `awsx stack plan` first",
+                    stack_name.as_ref()
+                )
+            })?,
+    };
+
+    // stacks that are created by a change set exist in REVIEW_IN_PROGRESS until it is executed
+    let stack_id = stack_id(&stack_name, config)?
+        .ok_or_else(|| anyhow::anyhow!("Stack {:?} does not exist", stack_name.as_ref()))?;
+    let since = latest_event(&stack_id, config)?;
+
     run(
         &format!(
-            "aws cloudformation wait stack-update-complete --stack-name {}",
-            stack_name.as_ref()
+            "aws cloudformation execute-change-set --stack-name {} --change-set-name {}",
+            stack_name.as_ref(),
+            change_set_name
         ),
         config,
     )?;
 
+    println!("Executing change set: {:?}", change_set_name);
+
+    watch(&stack_id, since, config)?;
+
+    if plan_path.is_file() {
+        std::fs::remove_file(&plan_path)?;
+    }
+
     println!("Done");
 
     Ok(())
 }
 
-pub fn destroy(stack_name: impl AsRef<str>, config: &Config) -> Result<()> {
-    run(
+/// The parameters of the template as arguments of the aws cli, and the values of its `NoEcho`
+/// parameters
+fn template_parameters(
+    template: impl AsRef<Path>,
+    config: &Config,
+) -> Result<(String, Vec<String>)> {
+    let parameters = get_parameters_from_config(template, config)?;
+
+    let sensitive = parameters
+        .iter()
+        .filter(|p| p.sensitive)
+        .map(|p| format_parameter_value(&p.key, &p.parameter_type, &p.value))
+        .filter(|v| !v.as_ref().is_ok_and(String::is_empty))
+        .collect::<Result<Vec<_>, _>>()?;
+    let parameters = parameters_to_string(&parameters)?;
+
+    Ok((parameters, sensitive))
+}
+
+/// Hides sensitive values in the message of an error, which may contain the command that failed
+fn redact(error: impl Into<anyhow::Error>, sensitive: &[String]) -> anyhow::Error {
+    let error = error.into();
+    if sensitive.is_empty() {
+        return error;
+    }
+
+    let message = sensitive
+        .iter()
+        .fold(format!("{:#}", error), |message, value| {
+            message.replace(value, "****")
+        });
+    anyhow::anyhow!(message)
+}
+
+/// Saves the name of a change set for `apply`. The `.awsx` directory ignores itself, so that
+/// plans are never committed along with the config.
+fn save_plan(plan_path: &Path, change_set_name: &str) -> Result<()> {
+    let plans = plan_path.parent().expect("plan paths have a parent");
+    std::fs::create_dir_all(plans)?;
+
+    if let Some(state_dir) = plans.parent() {
+        let gitignore = state_dir.join(".gitignore");
+        if !gitignore.exists() {
+            std::fs::write(gitignore, "*\n")?;
+        }
+    }
+
+    Ok(std::fs::write(plan_path, change_set_name)?)
+}
+
+/// Where `plan` saves the name of the change set of a stack
+fn plan_path(stack_name: impl AsRef<str>, config: &Config) -> PathBuf {
+    config
+        .config_dir()
+        .unwrap_or_else(|| PathBuf::from("."))
+        .join(".awsx")
+        .join("plans")
+        .join(stack_name.as_ref())
+}
+
+/// Prints the events of the stack, and of the stacks nested in it, that are newer than `since`
+/// until the operation on the stack completes. Fails with the events that caused a rollback.
+fn watch(stack_id: &str, since: Option<String>, config: &Config) -> Result<()> {
+    let colour = std::io::stdout().is_terminal() && std::env::var_os("NO_COLOR").is_none();
+
+    let mut stacks = vec![stack_id.to_owned()];
+    let mut seen = HashSet::new();
+    let mut events = Vec::new();
+
+    loop {
+        // the status is read first so that no events of a completed operation are missed
+        let status = capture(
+            &format!(
+                "aws cloudformation describe-stacks --stack-name {} --output text --query Stacks[0].StackStatus",
+                stack_id
+            ),
+            config,
+        )?;
+
+        let mut new_events = Vec::new();
+        let mut i = 0;
+        // nested stacks that are found along the way are polled in the same round
+        while i < stacks.len() {
+            let is_new = |event: &StackEvent| {
+                !seen.contains(&event.event_id)
+                    && since.as_ref().is_none_or(|since| &event.timestamp > since)
+            };
+            for event in stack_events(&stacks[i], is_new, config)? {
+                seen.insert(event.event_id.to_owned());
+                if event.is_nested_stack() && !stacks.contains(&event.physical_id) {
+                    stacks.push(event.physical_id.to_owned());
+                }
+                new_events.push(event);
+            }
+            i += 1;
+        }
+
+        new_events.sort_by(|a, b| a.timestamp.cmp(&b.timestamp));
+        for event in &new_events {
+            println!("{}", event.render(colour));
+        }
+        events.extend(new_events);
+
+        match operation_succeeded(status.trim()) {
+            None => std::thread::sleep(POLL_INTERVAL),
+            Some(true) => return Ok(()),
+            Some(false) => {
+                let causes = root_causes(&events)
+                    .into_iter()
+                    .map(|e| format!("  {}", e.render(false)))
+                    .collect::<Vec<_>>();
+                anyhow::bail!(
+                    "Stack ended in {}, caused by:\n{}",
+                    status.trim(),
+                    causes.join("\n")
+                );
+            }
+        }
+    }
+}
+
+/// The events of a stack that are new, newest first. Pages are read until reaching an event
+/// that is not new, so that no events are missed when many happen between two polls.
+fn stack_events(
+    stack_id: &str,
+    is_new: impl Fn(&StackEvent) -> bool,
+    config: &Config,
+) -> Result<Vec<StackEvent>> {
+    StackEvent::new_events(
+        |token| {
+            let starting_token = token
+                .map(|t| format!(" --starting-token '{}'", t))
+                .unwrap_or_default();
+            let events = capture(
+                &format!(
+                    "aws cloudformation describe-stack-events --stack-name {} --max-items 100 --output json{}",
+                    stack_id, starting_token
+                ),
+                config,
+            )?;
+
+            Ok(serde_json::from_str(&events)?)
+        },
+        is_new,
+    )
+}
+
+/// The timestamp of the latest event of a stack, to tell the events of a new operation apart
+fn latest_event(stack_id: &str, config: &Config) -> Result<Option<String>> {
+    let events = capture(
         &format!(
-            "aws cloudformation delete-stack --stack-name {}",
+            "aws cloudformation describe-stack-events --stack-name {} --max-items 1 --output json",
+            stack_id
+        ),
+        config,
+    )?;
+
+    Ok(StackEvent::from_json(&serde_json::from_str(&events)?)
+        .into_iter()
+        .next()
+        .map(|e| e.timestamp))
+}
+
+/// Returns the id of the stack, or `None` if it does not exist. Unlike its name, the id still
+/// refers to the stack once it is deleted.
+fn stack_id(stack_name: impl AsRef<str>, config: &Config) -> Result<Option<String>> {
+    let output = read_output(
+        &format!(
+            "aws cloudformation describe-stacks --stack-name {} --output text --query Stacks[0].StackId",
             stack_name.as_ref()
         ),
         config,
     )?;
 
-    println!("Deleting stack: {:?}", stack_name.as_ref());
-    println!("Waiting for completion...");
+    match output.status.success() {
+        true => Ok(Some(
+            String::from_utf8_lossy(&output.stdout).trim().to_owned(),
+        )),
+        false => Ok(None),
+    }
+}
+
+/// Returns the status of the stack, or `None` if it does not exist
+pub fn stack_status(stack_name: impl AsRef<str>, config: &Config) -> Result<Option<String>> {
+    let cmd = format!(
+        "aws cloudformation list-stacks --output text --query \"StackSummaries[?StackName=='{}' && StackStatus!='DELETE_COMPLETE'].StackStatus | [0]\"",
+        stack_name.as_ref()
+    );
+    let status = capture(&cmd, config)?;
+
+    match status.trim() {
+        "" | "None" => Ok(None),
+        status => Ok(Some(status.to_owned())),
+    }
+}
+
+/// Asks a yes/no question on the terminal, without a terminal the answer is no
+fn confirm(question: &str) -> Result<bool> {
+    if !std::io::stdin().is_terminal() {
+        return Ok(false);
+    }
+
+    print!("{} [y/N] ", question);
+    std::io::stdout().flush()?;
+
+    let mut answer = String::new();
+    std::io::stdin().lock().read_line(&mut answer)?;
+
+    Ok(matches!(answer.trim(), "y" | "Y" | "yes"))
+}
+
+pub fn destroy(stack_name: impl AsRef<str>, config: &Config) -> Result<()> {
+    let stack_id = match stack_id(&stack_name, config)? {
+        Some(stack_id) => stack_id,
+        None => {
+            println!("Stack {:?} does not exist", stack_name.as_ref());
+            return Ok(());
+        }
+    };
+    let since = latest_event(&stack_id, config)?;
 
     run(
         &format!(
-            "aws cloudformation wait stack-delete-complete --stack-name {}",
+            "aws cloudformation delete-stack --stack-name {}",
             stack_name.as_ref()
         ),
         config,
     )?;
 
+    println!("Deleting stack: {:?}", stack_name.as_ref());
+
+    watch(&stack_id, since, config)?;
+
     println!("Done");
 
     Ok(())
@@ -128,6 +542,9 @@ pub fn output(
 }
 
 pub fn validate(template: impl AsRef<Path>, config: &Config) -> Result<()> {
+    // syntax errors are reported without a call to AWS
+    Template::from_path(&template)?;
+
     let cmd = format!(
         "aws cloudformation validate-template --template-body file://{}",
         template.as_ref().to_string_lossy()
@@ -1,7 +1,9 @@
 pub use cli::*;
 pub use options::Subcommands;
+pub use template::Template;
 pub use util::Error;
 
 pub mod cli;
 pub mod options;
+pub mod template;
 pub mod util;
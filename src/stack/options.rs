@@ -23,6 +23,41 @@ pub enum Subcommands {
         template: PathBuf,
     },
 
+    /// Creates a Cloud Formation stack or updates it if it exists already
+    Deploy {
+        /// The name of the stack
+        stack_name: String,
+
+        /// Path to a Cloud Formation template file
+        #[clap(long, short = 't')]
+        template: PathBuf,
+
+        /// Delete and recreate a stack that failed to be created without asking
+        #[clap(long, short = 'y', action)]
+        yes: bool,
+    },
+
+    /// Creates a change set for a Cloud Formation stack and shows what it would change. Its name
+    /// is saved in `.awsx/plans` next to the config, which ignores itself in git.
+    Plan {
+        /// The name of the stack
+        stack_name: String,
+
+        /// Path to a Cloud Formation template file
+        #[clap(long, short = 't')]
+        template: PathBuf,
+    },
+
+    /// Executes the change set created by `plan`
+    Apply {
+        /// The name of the stack
+        stack_name: String,
+
+        /// Name of the change set to execute instead of the one saved by `plan`
+        #[clap(long)]
+        change_set: Option<String>,
+    },
+
     /// Destroys a Cloud Formation stack
     #[clap(visible_alias = "delete")]
     Destroy {
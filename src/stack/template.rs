@@ -0,0 +1,303 @@
+use super::util::{Error, ParameterDefinition};
+use serde_json::{Map, Number, Value};
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+use yaml_rust2::{
+    parser::{EventReceiver, Parser, Tag},
+    scanner::TScalarStyle,
+    Event, Yaml,
+};
+
+/// Intrinsic functions that have a short form tag in YAML templates, i.e. `!Sub` for `Fn::Sub`
+const SHORT_FORMS: &[&str] = &[
+    "Ref",
+    "Condition",
+    "And",
+    "Base64",
+    "Cidr",
+    "Equals",
+    "FindInMap",
+    "ForEach",
+    "GetAtt",
+    "GetAZs",
+    "If",
+    "ImportValue",
+    "Join",
+    "Length",
+    "Not",
+    "Or",
+    "Select",
+    "Split",
+    "Sub",
+    "ToJsonString",
+    "Transform",
+];
+
+/// A CloudFormation template read from a JSON or YAML file. The short forms of intrinsic
+/// functions, like `!Ref` or `!GetAtt`, are turned into their long forms, so that templates
+/// look the same in both formats.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Template {
+    path: PathBuf,
+    body: Value,
+}
+
+impl Template {
+    pub fn from_path(path: impl AsRef<Path>) -> Result<Self, Error> {
+        let contents = std::fs::read_to_string(&path).map_err(|e| Error::Io {
+            path: path.as_ref().into(),
+            source: e,
+        })?;
+
+        let is_json = path.as_ref().extension().is_some_and(|ext| ext == "json")
+            || contents.trim_start().starts_with('{');
+
+        let invalid = |msg: String| Error::InvalidTemplate {
+            path: path.as_ref().into(),
+            msg,
+        };
+
+        let body = match is_json {
+            true => serde_json::from_str(&contents).map_err(|e| invalid(e.to_string()))?,
+            false => yaml_to_json(&contents).map_err(invalid)?,
+        };
+
+        if !body.is_object() {
+            return Err(invalid("the template should be a mapping".to_owned()));
+        }
+
+        Ok(Template {
+            path: path.as_ref().into(),
+            body,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The whole template, with the long forms of all intrinsic functions
+    pub fn body(&self) -> &Value {
+        &self.body
+    }
+
+    /// A top level section of the template, like `Resources` or `Outputs`
+    pub fn section(&self, name: &str) -> Result<Option<&Map<String, Value>>, Error> {
+        match self.body.get(name) {
+            None | Some(Value::Null) => Ok(None),
+            Some(Value::Object(section)) => Ok(Some(section)),
+            Some(_) => Err(self.invalid(format!("{:?} should be a mapping", name))),
+        }
+    }
+
+    /// The declarations of the `Parameters` section, in the order of the template
+    pub fn parameters(&self) -> Result<Vec<ParameterDefinition>, Error> {
+        let section = match self.section("Parameters")? {
+            Some(section) => section,
+            None => return Ok(Vec::new()),
+        };
+
+        section
+            .iter()
+            .map(|(name, declaration)| match declaration {
+                Value::Object(declaration) => Ok(parameter_definition(name, declaration)),
+                _ => Err(self.invalid(format!("parameter {:?} should be a mapping", name))),
+            })
+            .collect()
+    }
+
+    fn invalid(&self, msg: String) -> Error {
+        Error::InvalidTemplate {
+            path: self.path.to_owned(),
+            msg,
+        }
+    }
+}
+
+fn parameter_definition(name: &str, declaration: &Map<String, Value>) -> ParameterDefinition {
+    let scalar = |value: &Value| match value {
+        Value::String(s) => Some(s.to_owned()),
+        Value::Number(n) => Some(n.to_string()),
+        Value::Bool(b) => Some(b.to_string()),
+        _ => None,
+    };
+    let field = |key: &str| declaration.get(key).and_then(scalar);
+
+    ParameterDefinition {
+        name: name.to_owned(),
+        parameter_type: field("Type").unwrap_or_else(|| "String".to_owned()),
+        default: field("Default"),
+        allowed_values: match declaration.get("AllowedValues") {
+            Some(Value::Array(values)) => values.iter().filter_map(scalar).collect(),
+            _ => Vec::new(),
+        },
+        allowed_pattern: field("AllowedPattern"),
+        min_length: field("MinLength").and_then(|v| v.parse().ok()),
+        max_length: field("MaxLength").and_then(|v| v.parse().ok()),
+        min_value: field("MinValue").and_then(|v| v.parse().ok()),
+        max_value: field("MaxValue").and_then(|v| v.parse().ok()),
+        no_echo: field("NoEcho").is_some_and(|v| v.eq_ignore_ascii_case("true")),
+    }
+}
+
+/// Parses a single YAML document. The parser events are used instead of a `Yaml` tree, as only
+/// they keep the tags of sequences and mappings, like in `!If [Condition, a, b]`.
+fn yaml_to_json(contents: &str) -> Result<Value, String> {
+    let mut builder = JsonBuilder::default();
+    Parser::new_from_str(contents)
+        .load(&mut builder, true)
+        .map_err(|e| e.to_string())?;
+
+    if let Some(error) = builder.error {
+        return Err(error);
+    }
+    match builder.documents.len() {
+        0 | 1 => Ok(builder.documents.pop().unwrap_or(Value::Null)),
+        _ => Err("expected a single YAML document".to_owned()),
+    }
+}
+
+enum Node {
+    Sequence(Vec<Value>),
+    /// The mapping so far and the key waiting for its value
+    Mapping(Map<String, Value>, Option<String>),
+}
+
+#[derive(Default)]
+struct JsonBuilder {
+    /// The sequences and mappings that are not closed yet, with their anchor and tag
+    stack: Vec<(Node, usize, Option<Tag>)>,
+    anchors: HashMap<usize, Value>,
+    documents: Vec<Value>,
+    error: Option<String>,
+}
+
+impl EventReceiver for JsonBuilder {
+    fn on_event(&mut self, event: Event) {
+        if self.error.is_none() {
+            if let Err(e) = self.handle(event) {
+                self.error = Some(e);
+            }
+        }
+    }
+}
+
+impl JsonBuilder {
+    fn handle(&mut self, event: Event) -> Result<(), String> {
+        match event {
+            Event::Scalar(s, style, anchor, tag) => {
+                let value = scalar_to_json(s, style, tag.as_ref())?;
+                self.close(value, anchor, tag)
+            }
+            Event::SequenceStart(anchor, tag) => {
+                self.stack.push((Node::Sequence(Vec::new()), anchor, tag));
+                Ok(())
+            }
+            Event::MappingStart(anchor, tag) => {
+                self.stack
+                    .push((Node::Mapping(Map::new(), None), anchor, tag));
+                Ok(())
+            }
+            Event::SequenceEnd | Event::MappingEnd => {
+                let (node, anchor, tag) = self.stack.pop().ok_or("unbalanced YAML")?;
+                let value = match node {
+                    Node::Sequence(s) => Value::Array(s),
+                    Node::Mapping(m, _) => Value::Object(m),
+                };
+                self.close(value, anchor, tag)
+            }
+            Event::Alias(anchor) => match self.anchors.get(&anchor) {
+                Some(value) => self.insert(value.to_owned()),
+                None => Err("unknown alias".to_owned()),
+            },
+            _ => Ok(()),
+        }
+    }
+
+    /// Applies the tag of a finished node and adds it to its parent
+    fn close(&mut self, value: Value, anchor: usize, tag: Option<Tag>) -> Result<(), String> {
+        let value = match tag {
+            Some(tag) if tag.handle == "!" => short_form_to_json(&tag.suffix, value)?,
+            Some(tag) if tag.handle == CORE_SCHEMA => value,
+            Some(tag) => return Err(format!("unknown tag {}{}", tag.handle, tag.suffix)),
+            None => value,
+        };
+
+        if anchor > 0 {
+            self.anchors.insert(anchor, value.to_owned());
+        }
+        self.insert(value)
+    }
+
+    fn insert(&mut self, value: Value) -> Result<(), String> {
+        match self.stack.last_mut() {
+            None => self.documents.push(value),
+            Some((Node::Sequence(s), ..)) => s.push(value),
+            Some((Node::Mapping(m, key @ Some(_)), ..)) => {
+                m.insert(key.take().expect("a pending key"), value);
+            }
+            Some((Node::Mapping(_, key), ..)) => {
+                *key = Some(match value {
+                    Value::String(s) => s,
+                    Value::Number(n) => n.to_string(),
+                    Value::Bool(b) => b.to_string(),
+                    k => return Err(format!("unsupported key {}", k)),
+                })
+            }
+        }
+        Ok(())
+    }
+}
+
+const CORE_SCHEMA: &str = "tag:yaml.org,2002:";
+
+/// Quoted scalars and `!!str` are strings, plain ones are resolved like the YAML core schema
+fn scalar_to_json(s: String, style: TScalarStyle, tag: Option<&Tag>) -> Result<Value, String> {
+    let is_string = style != TScalarStyle::Plain
+        || tag.is_some_and(|tag| tag.handle == CORE_SCHEMA && tag.suffix == "str");
+    if is_string {
+        return Ok(Value::String(s));
+    }
+
+    Ok(match Yaml::from_str(&s) {
+        Yaml::Null => Value::Null,
+        Yaml::Boolean(b) => Value::Bool(b),
+        Yaml::Integer(i) => Value::Number(i.into()),
+        Yaml::Real(r) => r
+            .parse()
+            .ok()
+            .and_then(Number::from_f64)
+            .map(Value::Number)
+            .ok_or_else(|| format!("unsupported number {}", r))?,
+        _ => Value::String(s),
+    })
+}
+
+fn short_form_to_json(name: &str, value: Value) -> Result<Value, String> {
+    if !SHORT_FORMS.contains(&name) {
+        return Err(format!("unknown tag !{}", name));
+    }
+
+    let value = match (name, value) {
+        // `!GetAtt Resource.Attribute` is the short form of a list of both
+        ("GetAtt", Value::String(s)) => match s.split_once('.') {
+            Some((resource, attribute)) => Value::Array(vec![
+                Value::String(resource.to_owned()),
+                Value::String(attribute.to_owned()),
+            ]),
+            None => return Err(format!("invalid !GetAtt {:?}", s)),
+        },
+        (_, value) => value,
+    };
+
+    let key = match name {
+        "Ref" | "Condition" => name.to_owned(),
+        _ => format!("Fn::{}", name),
+    };
+
+    let mut map = Map::new();
+    map.insert(key, value);
+    Ok(Value::Object(map))
+}
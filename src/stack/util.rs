@@ -1,16 +1,22 @@
-use crate::{cmd::read_with_dir, config::Config};
+use super::template::Template;
+use crate::{
+    cmd::get_envs_with_config_envs,
+    config::{as_expression, Config},
+};
 use std::path::{Path, PathBuf};
 use toml::Value;
-use yaml_rust::{Yaml, YamlLoader};
 
 #[derive(Debug, thiserror::Error)]
 pub enum Error {
-    #[error("Invalid yaml syntax")]
-    InvalidYaml(#[from] yaml_rust::ScanError),
+    #[error("Invalid template {:?}: {}", path, msg)]
+    InvalidTemplate { path: PathBuf, msg: String },
 
     #[error("Missing parameter in config: {:?}", key)]
     MissingParameter { key: String },
 
+    #[error("Invalid value for parameter {:?}: {}", key, msg)]
+    InvalidParameter { key: String, msg: String },
+
     #[error("Problem with path: {:?}", path)]
     Io {
         path: PathBuf,
@@ -20,20 +26,443 @@ pub enum Error {
 
     #[error(transparent)]
     Cmd(#[from] crate::cmd::Error),
+
+    #[error(transparent)]
+    Config(#[from] crate::config::Error),
 }
 
-pub fn parameters_to_string(parameters: Vec<(String, Value)>) -> String {
-    [String::from("--parameters")]
-        .into_iter()
-        .chain(parameters.into_iter().map(|(k, v)| match v {
-            Value::Table(t) => match t.get("value") {
-                Some(v) => format!("ParameterKey={},ParameterValue={}", k, v),
-                None => panic!("missing 'value' key in table: {k} - {:?}", t),
-            },
-            _ => format!("ParameterKey={},ParameterValue={}", k, v),
-        }))
-        .collect::<Vec<String>>()
-        .join(" ")
+/// What `deploy` has to do with a stack, depending on its current status
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DeployAction {
+    Create,
+    Update,
+    /// The stack failed to be created and can only be deleted
+    Recreate,
+    /// The stack was planned with a change set that was never executed, so it has no resources
+    Review,
+    /// Another operation is still running
+    Busy(String),
+    /// The stack needs manual intervention before it can be updated again
+    Blocked(String),
+}
+
+/// Maps the status of a stack to the action that deploys it, `None` means it does not exist
+pub fn deploy_action(status: Option<&str>) -> DeployAction {
+    match status {
+        None | Some("DELETE_COMPLETE") => DeployAction::Create,
+        Some("ROLLBACK_COMPLETE") => DeployAction::Recreate,
+        Some("REVIEW_IN_PROGRESS") => DeployAction::Review,
+        Some(s) if s.ends_with("_IN_PROGRESS") => DeployAction::Busy(s.to_owned()),
+        Some(s) if s.ends_with("_FAILED") => DeployAction::Blocked(s.to_owned()),
+        Some(_) => DeployAction::Update,
+    }
+}
+
+/// Renders the resource changes listed in the output of `aws cloudformation describe-change-set`
+/// as one line per resource, followed by the properties that change and a summary
+pub fn render_change_set(change_set: &serde_json::Value) -> String {
+    let str_at = |value: &serde_json::Value, pointer: &str| {
+        value
+            .pointer(pointer)
+            .and_then(|v| v.as_str())
+            .unwrap_or_default()
+            .to_owned()
+    };
+
+    let changes = change_set
+        .get("Changes")
+        .and_then(|c| c.as_array())
+        .cloned()
+        .unwrap_or_default();
+
+    let mut lines = Vec::new();
+    let mut counts = [0, 0, 0];
+
+    for change in &changes {
+        let action = str_at(change, "/ResourceChange/Action");
+        let (symbol, count) = match action.as_str() {
+            "Add" => ("+", &mut counts[0]),
+            "Remove" => ("-", &mut counts[2]),
+            _ => ("~", &mut counts[1]),
+        };
+        *count += 1;
+
+        let mut line = format!(
+            "{} {:<8}{} ({})",
+            symbol,
+            action,
+            str_at(change, "/ResourceChange/LogicalResourceId"),
+            str_at(change, "/ResourceChange/ResourceType")
+        );
+        match str_at(change, "/ResourceChange/Replacement").as_str() {
+            "True" => line.push_str(" [replacement]"),
+            "Conditional" => line.push_str(" [may require replacement]"),
+            _ => {}
+        }
+        lines.push(line);
+
+        let details = change
+            .pointer("/ResourceChange/Details")
+            .and_then(|d| d.as_array())
+            .cloned()
+            .unwrap_or_default();
+        for detail in details {
+            let target = match str_at(&detail, "/Target/Name").as_str() {
+                "" => str_at(&detail, "/Target/Attribute"),
+                name => format!("{}.{}", str_at(&detail, "/Target/Attribute"), name),
+            };
+            let mut line = format!("      {}", target);
+            match str_at(&detail, "/Target/RequiresRecreation").as_str() {
+                "" | "Never" => {}
+                recreation => line.push_str(&format!(", requires recreation: {}", recreation)),
+            }
+            match (
+                str_at(&detail, "/ChangeSource"),
+                str_at(&detail, "/CausingEntity"),
+            ) {
+                (source, _) if source.is_empty() => {}
+                (source, entity) if entity.is_empty() => line.push_str(&format!(", {}", source)),
+                (source, entity) => line.push_str(&format!(", {} {}", source, entity)),
+            }
+            lines.push(line);
+        }
+    }
+
+    lines.push(format!(
+        "{} to add, {} to modify, {} to remove",
+        counts[0], counts[1], counts[2]
+    ));
+
+    lines.join("\n")
+}
+
+/// An entry of `aws cloudformation describe-stack-events`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StackEvent {
+    pub event_id: String,
+    pub stack_id: String,
+    pub timestamp: String,
+    pub logical_id: String,
+    pub physical_id: String,
+    pub resource_type: String,
+    pub status: String,
+    pub reason: Option<String>,
+}
+
+impl StackEvent {
+    /// Reads the events of the output of `aws cloudformation describe-stack-events --output json`,
+    /// newest first like CloudFormation lists them
+    pub fn from_json(events: &serde_json::Value) -> Vec<StackEvent> {
+        let events = match events.get("StackEvents").and_then(|e| e.as_array()) {
+            Some(events) => events,
+            None => return Vec::new(),
+        };
+
+        events
+            .iter()
+            .map(|event| {
+                let field = |name: &str| event[name].as_str().unwrap_or_default().to_owned();
+                StackEvent {
+                    event_id: field("EventId"),
+                    stack_id: field("StackId"),
+                    timestamp: field("Timestamp"),
+                    logical_id: field("LogicalResourceId"),
+                    physical_id: field("PhysicalResourceId"),
+                    resource_type: field("ResourceType"),
+                    status: field("ResourceStatus"),
+                    reason: event["ResourceStatusReason"]
+                        .as_str()
+                        .map(ToOwned::to_owned),
+                }
+            })
+            .collect()
+    }
+
+    /// Reads pages of `describe-stack-events` output until reaching an event that is not new or
+    /// the last page. `next_page` gets the `NextToken` of the previous page, events that are
+    /// not new end the listing as all later ones are older. Returns the new events, newest first.
+    pub fn new_events<E>(
+        mut next_page: impl FnMut(Option<&str>) -> Result<serde_json::Value, E>,
+        is_new: impl Fn(&StackEvent) -> bool,
+    ) -> Result<Vec<StackEvent>, E> {
+        let mut events = Vec::new();
+        let mut token = None;
+
+        loop {
+            let page = next_page(token.as_deref())?;
+            let page_events = StackEvent::from_json(&page);
+            let complete = page_events.iter().any(|e| !is_new(e));
+            events.extend(page_events.into_iter().take_while(|e| is_new(e)));
+
+            token = page["NextToken"].as_str().map(ToOwned::to_owned);
+            if complete || token.is_none() {
+                return Ok(events);
+            }
+        }
+    }
+
+    /// Whether this event is about a stack nested in the stack that reports it
+    pub fn is_nested_stack(&self) -> bool {
+        self.resource_type == "AWS::CloudFormation::Stack"
+            && !self.physical_id.is_empty()
+            && self.physical_id != self.stack_id
+    }
+
+    pub fn is_failure(&self) -> bool {
+        self.status.ends_with("_FAILED")
+    }
+
+    /// A line with the timestamp, logical id, resource type, status and reason of the event.
+    /// With `colour`, the status is green for completed, red for failed and rolled back and
+    /// yellow for running operations.
+    pub fn render(&self, colour: bool) -> String {
+        let status = match colour {
+            false => self.status.to_owned(),
+            true => {
+                let code = match self.status.as_str() {
+                    s if s.ends_with("_FAILED") || s.contains("ROLLBACK") => "31",
+                    s if s.ends_with("_IN_PROGRESS") => "33",
+                    _ => "32",
+                };
+                format!("\x1b[{}m{}\x1b[0m", code, self.status)
+            }
+        };
+
+        let mut line = format!(
+            "{}  {}  {}  {}",
+            self.timestamp, self.logical_id, self.resource_type, status
+        );
+        if let Some(reason) = &self.reason {
+            line.push_str("  ");
+            line.push_str(reason);
+        }
+        line
+    }
+}
+
+/// How an operation on a stack ended, by the status the stack is left in. `None` means that
+/// it is still running.
+pub fn operation_succeeded(status: &str) -> Option<bool> {
+    match status {
+        s if s.ends_with("_IN_PROGRESS") => None,
+        s if s.contains("ROLLBACK") || s.ends_with("_FAILED") => Some(false),
+        _ => Some(true),
+    }
+}
+
+/// The failures that caused an operation to roll back, without the ones that only follow from
+/// them: cancelled resources and the stacks, nested or not, that failed because of a resource
+pub fn root_causes(events: &[StackEvent]) -> Vec<&StackEvent> {
+    let failures = events.iter().filter(|e| e.is_failure()).collect::<Vec<_>>();
+
+    let causes = failures
+        .iter()
+        .copied()
+        .filter(|e| e.resource_type != "AWS::CloudFormation::Stack")
+        .filter(|e| {
+            !e.reason
+                .as_deref()
+                .unwrap_or_default()
+                .to_lowercase()
+                .contains("cancelled")
+        })
+        .collect::<Vec<_>>();
+
+    match causes.is_empty() {
+        true => failures,
+        false => causes,
+    }
+}
+
+/// The `--parameters` argument of the aws cli for the parameters, nothing if there are none
+pub fn parameters_to_string(parameters: &[Parameter]) -> Result<String, Error> {
+    if parameters.is_empty() {
+        return Ok(String::new());
+    }
+
+    let arguments = parameters
+        .iter()
+        .map(|p| {
+            let value = format_parameter_value(&p.key, &p.parameter_type, &p.value)?;
+            // commas separate the fields of the shorthand syntax, so the ones of lists are escaped
+            let argument = format!(
+                "ParameterKey={},ParameterValue={}",
+                p.key,
+                value.replace(',', "\\,")
+            );
+            Ok(shell_quote(&argument))
+        })
+        .collect::<Result<Vec<_>, Error>>()?;
+
+    Ok(format!("--parameters {}", arguments.join(" ")))
+}
+
+/// Formats a value of the config the way CloudFormation expects it for a parameter of the
+/// given type: lists are joined with commas and all other values are passed as they are.
+/// Arrays are only allowed for list types, tables and datetimes never.
+pub fn format_parameter_value(
+    key: &str,
+    parameter_type: &str,
+    value: &Value,
+) -> Result<String, Error> {
+    let invalid = |msg: String| Error::InvalidParameter {
+        key: key.to_owned(),
+        msg,
+    };
+
+    match value {
+        Value::String(s) => Ok(s.to_owned()),
+        Value::Integer(_) | Value::Float(_) | Value::Boolean(_) => Ok(value.to_string()),
+        Value::Array(items) if is_list_type(parameter_type) => items
+            .iter()
+            .map(|item| match item {
+                Value::Array(_) | Value::Table(_) | Value::Datetime(_) => Err(invalid(format!(
+                    "lists can only contain strings, numbers and booleans, found {}",
+                    item
+                ))),
+                item => format_parameter_value(key, parameter_type, item),
+            })
+            .collect::<Result<Vec<_>, _>>()
+            .map(|items| items.join(",")),
+        Value::Array(_) => Err(invalid(format!(
+            "a list can not be passed to a parameter of type {}",
+            parameter_type
+        ))),
+        Value::Table(_) => Err(invalid(
+            "a table can not be passed as a parameter".to_owned(),
+        )),
+        Value::Datetime(d) => Err(invalid(format!(
+            "datetime {} can not be passed as a parameter, use a string",
+            d
+        ))),
+    }
+}
+
+fn is_list_type(parameter_type: &str) -> bool {
+    parameter_type.starts_with("List<") || parameter_type == "CommaDelimitedList"
+}
+
+/// Quotes an argument for bash unless it only contains characters that are safe without
+fn shell_quote(argument: &str) -> String {
+    let safe = |c: char| c.is_ascii_alphanumeric() || "_-.,=/:@+".contains(c);
+    match argument.chars().all(safe) {
+        true => argument.to_owned(),
+        false => format!("'{}'", argument.replace('\'', "'\\''")),
+    }
+}
+
+/// A parameter of a template with the value it gets from the config
+#[derive(Debug, Clone, PartialEq)]
+pub struct Parameter {
+    pub key: String,
+    /// The `Type` of the parameter in the template
+    pub parameter_type: String,
+    pub value: Value,
+    /// The parameter is declared with `NoEcho`, its value must not be shown
+    pub sensitive: bool,
+}
+
+/// A parameter as it is declared in the `Parameters` section of a template
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ParameterDefinition {
+    pub name: String,
+    pub parameter_type: String,
+    pub default: Option<String>,
+    pub allowed_values: Vec<String>,
+    pub allowed_pattern: Option<String>,
+    pub min_length: Option<usize>,
+    pub max_length: Option<usize>,
+    pub min_value: Option<f64>,
+    pub max_value: Option<f64>,
+    pub no_echo: bool,
+}
+
+impl ParameterDefinition {
+    /// Checks a value of the config against the constraints of the parameter, like CloudFormation
+    /// would. List parameters check each of their comma separated items.
+    pub fn validate(&self, value: &Value) -> Result<(), Error> {
+        let invalid = |msg: String| Error::InvalidParameter {
+            key: self.name.to_owned(),
+            msg,
+        };
+
+        // the values of NoEcho parameters must not end up in terminals and logs
+        let shown = |item: &str| match self.no_echo {
+            true => "****".to_owned(),
+            false => format!("{:?}", item),
+        };
+
+        let value = format_parameter_value(&self.name, &self.parameter_type, value)?;
+
+        let is_list = is_list_type(&self.parameter_type);
+        let is_number = self.parameter_type == "Number" || self.parameter_type == "List<Number>";
+
+        let items = match is_list {
+            true => value.split(',').map(str::trim).collect::<Vec<_>>(),
+            false => vec![value.as_str()],
+        };
+
+        for item in items {
+            if !self.allowed_values.is_empty() && !self.allowed_values.iter().any(|v| v == item) {
+                return Err(invalid(format!(
+                    "{} is not one of {:?}",
+                    shown(item),
+                    self.allowed_values
+                )));
+            }
+
+            if let Some(pattern) = &self.allowed_pattern {
+                // CloudFormation matches the pattern against the whole value
+                let regex = regex::Regex::new(&format!("^(?:{})$", pattern))
+                    .map_err(|e| invalid(format!("invalid AllowedPattern: {}", e)))?;
+                if !regex.is_match(item) {
+                    return Err(invalid(format!(
+                        "{} does not match {:?}",
+                        shown(item),
+                        pattern
+                    )));
+                }
+            }
+
+            let length = item.chars().count();
+            if self.min_length.is_some_and(|min| length < min) {
+                return Err(invalid(format!(
+                    "{} is shorter than {} characters",
+                    shown(item),
+                    self.min_length.unwrap_or_default()
+                )));
+            }
+            if self.max_length.is_some_and(|max| length > max) {
+                return Err(invalid(format!(
+                    "{} is longer than {} characters",
+                    shown(item),
+                    self.max_length.unwrap_or_default()
+                )));
+            }
+
+            if is_number {
+                let number = item
+                    .parse::<f64>()
+                    .map_err(|_| invalid(format!("{} is not a number", shown(item))))?;
+                if self.min_value.is_some_and(|min| number < min) {
+                    return Err(invalid(format!(
+                        "{} is less than {}",
+                        shown(item),
+                        self.min_value.unwrap_or_default()
+                    )));
+                }
+                if self.max_value.is_some_and(|max| number > max) {
+                    return Err(invalid(format!(
+                        "{} is greater than {}",
+                        shown(item),
+                        self.max_value.unwrap_or_default()
+                    )));
+                }
+            }
+        }
+
+        Ok(())
+    }
 }
 
 /// Only gets the necessary parameters that it finds in the template file
@@ -41,71 +470,77 @@ pub fn get_parameter_values_from_config(
     template: impl AsRef<Path>,
     config: &Config,
 ) -> Result<Vec<(String, Value)>, Error> {
-    extract_parameter_keys_from_template(template)?
+    Ok(get_parameters_from_config(template, config)?
         .into_iter()
-        .map(|key| {
-            config
-                .get_with_filepath(&format!("parameters.{}", key))
-                .map(|(val, filepath)| (key.clone(), val.clone(), filepath))
-                .ok_or(Error::MissingParameter { key })
-        })
-        .flat_map(|r| {
-            r.map(|(key, val, filepath)| {
-                let val = match val {
-                    Value::Table(t) => match t.get("value") {
-                        Some(v) => v.to_owned(),
-                        None => panic!("missing 'value' key in table: {key} - {:?}", t),
-                    },
-                    _ => val,
-                };
+        .map(|p| (p.key, p.value))
+        .collect())
+}
+
+/// Gets the values of the parameters of the template from the config and validates them.
+/// Parameters with a `Default` may be left out of the config, CloudFormation uses the default.
+pub fn get_parameters_from_config(
+    template: impl AsRef<Path>,
+    config: &Config,
+) -> Result<Vec<Parameter>, Error> {
+    let mut parameters = Vec::new();
+
+    for definition in extract_parameters_from_template(template)? {
+        let key = definition.name.to_owned();
+        let (val, filepath) = match config.get_with_filepath(format!("parameters.{}", key)) {
+            Some((val, filepath)) => (val.to_owned(), filepath),
+            None if definition.default.is_some() => continue,
+            None => return Err(Error::MissingParameter { key }),
+        };
 
-                if let Some(s) = val.as_str() {
-                    if s.starts_with("{{") && s.ends_with("}}") {
-                        let exp = s[2..s.len() - 2].trim();
-                        return read_with_dir(
-                            exp,
-                            filepath.parent().expect("has a parent"),
-                            config,
-                        )
-                        .map(|val| (key, Value::String(val)))
-                        .map_err(Error::Cmd);
-                    }
+        let (val, value_key) = match val {
+            Value::Table(t) if !t.contains_key("encrypted") => match t.get("value") {
+                Some(v) => (v.to_owned(), format!("parameters.{}.value", key)),
+                None => {
+                    return Err(Error::InvalidParameter {
+                        key,
+                        msg: "a table needs a 'value' key".to_owned(),
+                    })
                 }
+            },
+            _ => (val, format!("parameters.{}", key)),
+        };
+        let filepath = config.defining_file(&value_key, filepath);
 
-                Ok((key, val))
-            })
-        })
-        .collect::<Result<Vec<_>, Error>>()
+        let value = if let Some(plaintext) = config.decrypt(value_key, &val)? {
+            Value::String(plaintext)
+        } else if let Some(exp) = val.as_str().and_then(as_expression) {
+            get_envs_with_config_envs(config)
+                .and_then(|env| config.evaluate_expression(exp, &filepath, &env))
+                .map(Value::String)?
+        } else {
+            val
+        };
+
+        definition.validate(&value)?;
+
+        parameters.push(Parameter {
+            key,
+            parameter_type: definition.parameter_type.to_owned(),
+            value,
+            sensitive: definition.no_echo,
+        });
+    }
+
+    Ok(parameters)
 }
 
 pub fn extract_parameter_keys_from_template(
     template: impl AsRef<Path>,
 ) -> Result<Vec<String>, Error> {
-    let yaml_str = std::fs::read_to_string(&template).map_err(|e| Error::Io {
-        path: template.as_ref().into(),
-        source: e,
-    })?;
-    let mut roots = YamlLoader::load_from_str(&yaml_str)?;
-
-    assert_eq!(
-        roots.len(),
-        1,
-        "multiple entrypoints found in template file"
-    );
-
-    Ok(roots
-        .remove(0)
-        .into_hash()
-        .expect("tree structure at root")
-        .remove(&Yaml::String("Parameters".to_string()))
-        .map_or(Vec::new(), |x| {
-            x.into_hash()
-                .expect("Expected \"Parameters\" entry to have sub entries")
-                .into_iter()
-                .map(|(k, _v)| match k {
-                    Yaml::String(k) => k,
-                    _ => unreachable!("expected ”String” as keys"),
-                })
-                .collect()
-        }))
+    Ok(extract_parameters_from_template(template)?
+        .into_iter()
+        .map(|p| p.name)
+        .collect())
+}
+
+/// Reads the declarations of the `Parameters` section of the template
+pub fn extract_parameters_from_template(
+    template: impl AsRef<Path>,
+) -> Result<Vec<ParameterDefinition>, Error> {
+    Template::from_path(template)?.parameters()
 }
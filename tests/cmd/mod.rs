@@ -1,5 +1,5 @@
 use awsx::{
-    cmd::{read, read_with_dir, run},
+    cmd::{capture, read, read_output, read_with_dir, run, Error},
     config::Config,
 };
 use std::path::PathBuf;
@@ -43,3 +43,23 @@ fn can_run_cmd_with_workdir() {
     .join("tests");
     assert_eq!(actual, expected.to_string_lossy());
 }
+
+#[test]
+fn output_is_captured_even_if_silent() {
+    let mut config = Config::new();
+    config.set_string("env.AWS_PROFILE", "default");
+    config.set_string("env.AWS_DEFAULT_REGION", "eu-central-1");
+    config.set_bool("cmd.silent", true);
+
+    assert_eq!(capture("echo testing", &config).unwrap(), "testing");
+    assert!(matches!(capture("true", &config), Err(Error::NoOutput)));
+    assert!(matches!(
+        capture("echo oops >&2; false", &config),
+        Err(Error::CommandFailed { stderr, .. }) if stderr == "oops"
+    ));
+
+    let output = read_output("echo out; echo err >&2; false", &config).unwrap();
+    assert!(!output.status.success());
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "out\n");
+    assert_eq!(String::from_utf8_lossy(&output.stderr), "err\n");
+}
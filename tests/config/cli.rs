@@ -0,0 +1,103 @@
+use crate::tools::{fixture_path, temp_fixture};
+use awsx::config::{Config, Options};
+use toml::Value;
+
+#[test]
+fn get_all_layers_of_a_key() {
+    let path = fixture_path("nested_configs/sub/config.toml");
+    let config = Config::from_path(path, Default::default()).unwrap();
+
+    let layers = config.get_all_with_filepath("env.AWS_PROFILE");
+    let values = layers
+        .iter()
+        .map(|(v, _)| v.as_str().unwrap())
+        .collect::<Vec<_>>();
+
+    assert_eq!(values, vec!["edited", "default"]);
+    assert_eq!(
+        layers[0].1,
+        fixture_path("nested_configs/sub/config.toml")
+            .canonicalize()
+            .unwrap()
+    );
+    assert_eq!(
+        layers[1].1,
+        fixture_path("nested_configs/config.toml")
+            .canonicalize()
+            .unwrap()
+    );
+
+    assert!(config.get_all_with_filepath("non_existent").is_empty());
+}
+
+#[test]
+fn list_all_keys() {
+    let path = fixture_path("nested_configs/sub/config.toml");
+    let config = Config::from_path(path, Default::default()).unwrap();
+
+    assert_eq!(
+        config.keys(),
+        vec![
+            "array",
+            "env.AWS_DEFAULT_REGION",
+            "env.AWS_PROFILE",
+            "list.values",
+            "sub.a.var_b",
+            "sub.b.var_c",
+            "sub.var_a",
+            "var_a",
+            "var_b",
+            "var_c",
+        ]
+    );
+}
+
+#[test]
+fn parse_values() {
+    assert_eq!(Config::parse_value("42"), Value::Integer(42));
+    assert_eq!(Config::parse_value("true"), Value::Boolean(true));
+    assert_eq!(
+        Config::parse_value("\"quoted\""),
+        Value::String("quoted".to_string())
+    );
+    assert_eq!(
+        Config::parse_value("unquoted"),
+        Value::String("unquoted".to_string())
+    );
+    assert_eq!(
+        Config::parse_value("[\"a\", \"b\"]"),
+        Value::Array(vec![
+            Value::String("a".to_string()),
+            Value::String("b".to_string())
+        ])
+    );
+}
+
+#[test]
+fn set_value_in_file() {
+    let dir = temp_fixture("nested_configs", "set_value_in_file");
+    let path = dir.join("config.toml");
+    let options = Options {
+        nested: false,
+        ..Default::default()
+    };
+    let mut config = Config::from_path(&path, options.clone()).unwrap();
+
+    config
+        .set_in_file("sub.a.var_d", Value::Integer(42), &path)
+        .unwrap();
+    config
+        .set_in_file("env.AWS_PROFILE", Value::String("written".into()), &path)
+        .unwrap();
+
+    assert_eq!(config.get_int("sub.a.var_d"), Some(&42));
+    assert_eq!(config.get_string("env.AWS_PROFILE").unwrap(), "written");
+
+    let reloaded = Config::from_path(&path, options).unwrap();
+    assert_eq!(reloaded.get_int("sub.a.var_d"), Some(&42));
+    assert_eq!(reloaded.get_string("sub.a.var_b").unwrap(), "uvw");
+    assert_eq!(reloaded.get_string("env.AWS_PROFILE").unwrap(), "written");
+
+    let r = config.set_in_file("var_a.invalid", Value::Integer(1), &path);
+    assert!(matches!(r, Err(awsx::config::Error::Write { .. })));
+}
@@ -0,0 +1,109 @@
+use crate::tools::{fixture_path, temp_fixture};
+use age::{secrecy::ExposeSecret, x25519::Identity};
+use awsx::config::{Change, Config, Options};
+use toml::Value;
+
+fn load(name: &str) -> Config {
+    let options = Options {
+        nested: false,
+        ..Default::default()
+    };
+    Config::from_path(fixture_path(&format!("diff/{}/config.toml", name)), options).unwrap()
+}
+
+#[test]
+fn diff_merged_values() {
+    let staging = load("staging");
+    let production = load("production");
+
+    let changes = staging.diff(&production, false, false).unwrap();
+
+    assert_eq!(
+        changes,
+        vec![
+            Change::Changed {
+                key: "env.STAGE".into(),
+                from: Value::String("staging".into()),
+                to: Value::String("production".into()),
+            },
+            Change::Removed {
+                key: "parameters.DebugLogs".into(),
+                value: Value::Boolean(true),
+            },
+            Change::Changed {
+                key: "parameters.InstanceType".into(),
+                from: Value::String("t3.micro".into()),
+                to: Value::String("m5.large".into()),
+            },
+            Change::Added {
+                key: "parameters.MinInstances".into(),
+                value: Value::Integer(2),
+            },
+        ]
+    );
+}
+
+#[test]
+fn diff_resolved_values() {
+    let staging = load("staging");
+    let production = load("production");
+
+    let changes = staging.diff(&production, true, false).unwrap();
+
+    assert_eq!(
+        changes.first(),
+        Some(&Change::Changed {
+            key: "env.DATABASE".into(),
+            from: Value::String("staging-db".into()),
+            to: Value::String("production-db".into()),
+        })
+    );
+    assert_eq!(changes.len(), 5);
+}
+
+#[test]
+fn no_changes() {
+    let staging = load("staging");
+
+    assert_eq!(staging.diff(&load("staging"), true, false).unwrap(), vec![]);
+}
+
+#[test]
+fn encrypted_values_are_only_decrypted_on_request() {
+    let dir = temp_fixture("secrets", "encrypted_values_are_only_decrypted_on_request");
+    let path = dir.join("config.toml");
+    std::fs::write(
+        dir.join("age.key"),
+        Identity::generate().to_string().expose_secret(),
+    )
+    .unwrap();
+
+    let mut config = Config::from_path(&path, Default::default()).unwrap();
+    let encrypted = config.encrypt("env.PASSWORD", "hunter2", None).unwrap();
+    config
+        .set_in_file("env.PASSWORD", encrypted, &path)
+        .unwrap();
+
+    let options = Options {
+        nested: false,
+        ..Default::default()
+    };
+    let config = Config::from_path(&path, options).unwrap();
+
+    let changes = config.diff(&Config::new(), true, true).unwrap();
+    assert!(changes.contains(&Change::Removed {
+        key: "env.PASSWORD".into(),
+        value: Value::String("hunter2".into()),
+    }));
+
+    // without decrypting, the identity is not needed
+    std::fs::remove_file(dir.join("age.key")).unwrap();
+    let changes = config.diff(&Config::new(), true, false).unwrap();
+    assert!(changes
+        .iter()
+        .any(|change| change.key() == "env.PASSWORD.encrypted"));
+    assert!(!changes.iter().any(|change| matches!(
+        change,
+        Change::Removed { value, .. } if value.as_str() == Some("hunter2")
+    )));
+}
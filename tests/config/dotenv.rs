@@ -0,0 +1,49 @@
+use crate::tools::fixture_path;
+use awsx::config::{Config, Options};
+
+fn load() -> Config {
+    let path = fixture_path("dotenv/config.toml");
+    let options = Options {
+        nested: false,
+        ..Default::default()
+    };
+    Config::from_path(path, options).unwrap()
+}
+
+#[test]
+fn imports_env_files() {
+    let config = load();
+    let envs = config.get_envs();
+
+    assert_eq!(envs.get("AWS_PROFILE").unwrap(), "dotenv");
+    assert_eq!(envs.get("AWS_DEFAULT_REGION").unwrap(), "eu-central-1");
+    assert_eq!(
+        envs.get("DATABASE_URL").unwrap(),
+        "postgres://localhost:5432/db"
+    );
+}
+
+#[test]
+fn env_table_takes_precedence() {
+    let config = load();
+
+    assert_eq!(config.get_string("env.EXPLICIT").unwrap(), "from config");
+}
+
+#[test]
+fn later_env_files_take_precedence() {
+    let config = load();
+
+    assert_eq!(config.get_string("env.OVERRIDDEN").unwrap(), "second");
+}
+
+#[test]
+fn env_files_share_the_precedence_of_their_config() {
+    let config = load();
+
+    let (_, filepath) = config.get_with_filepath("env.AWS_PROFILE").unwrap();
+    assert_eq!(
+        filepath,
+        fixture_path("dotenv/config.toml").canonicalize().unwrap()
+    );
+}
@@ -0,0 +1,95 @@
+use crate::tools::fixture_path;
+use awsx::config::{Config, Error, Options};
+
+fn load_environment(name: &str) -> Result<Config, Error> {
+    let path = fixture_path("environments/config.toml");
+    let options = Options {
+        environment: Some(name.to_string()),
+        ..Default::default()
+    };
+    Config::from_path(path, options)
+}
+
+#[test]
+fn without_environment() {
+    let path = fixture_path("environments/config.toml");
+    let config = Config::from_path(path, Default::default()).unwrap();
+
+    assert_eq!(config.get_string("var_a").unwrap(), "abc");
+    assert_eq!(config.get_string("env.AWS_PROFILE").unwrap(), "default");
+    assert_eq!(
+        config.get_string("parameters.InstanceType").unwrap(),
+        "t3.nano"
+    );
+}
+
+#[test]
+fn with_extended_environment() {
+    let config = load_environment("staging").unwrap();
+
+    assert_eq!(config.get_string("var_a").unwrap(), "base");
+    assert_eq!(config.get_string("env.AWS_PROFILE").unwrap(), "staging");
+    assert_eq!(
+        config.get_string("env.AWS_DEFAULT_REGION").unwrap(),
+        "eu-central-1"
+    );
+    assert_eq!(
+        config.get_string("parameters.InstanceType").unwrap(),
+        "t3.small"
+    );
+    assert_eq!(config.get_string("parameters.ImageTag").unwrap(), "latest");
+    assert_eq!(config.get_envs().get("AWS_PROFILE").unwrap(), "staging");
+}
+
+#[test]
+fn with_overwritten_values() {
+    let config = load_environment("production").unwrap();
+
+    assert_eq!(config.get_string("var_a").unwrap(), "production");
+    assert_eq!(
+        config.get_string("parameters.InstanceType").unwrap(),
+        "t3.small"
+    );
+    assert_eq!(config.get_string("parameters.ImageTag").unwrap(), "v1.0.0");
+}
+
+#[test]
+fn environment_as_provenance() {
+    let config = load_environment("production").unwrap();
+
+    let (_, filepath) = config.get_with_filepath("env.AWS_PROFILE").unwrap();
+    assert_eq!(filepath, Config::environment_path("production"));
+
+    let (_, filepath) = config.get_with_filepath("env.AWS_DEFAULT_REGION").unwrap();
+    assert_eq!(
+        filepath,
+        fixture_path("environments/config.toml")
+            .canonicalize()
+            .unwrap()
+    );
+}
+
+#[test]
+fn unknown_environment() {
+    let r = load_environment("unknown");
+
+    assert!(matches!(r, Err(Error::UnknownEnvironment { name }) if name == "unknown"));
+}
+
+#[test]
+fn cyclic_environments() {
+    let r = load_environment("cyclic_a");
+
+    assert!(matches!(r, Err(Error::InvalidEnvironment { .. })));
+}
+
+#[test]
+fn expressions_run_in_the_directory_of_their_config_file() {
+    let config = load_environment("staging").unwrap();
+
+    let expected = std::fs::canonicalize(fixture_path("environments")).unwrap();
+    assert_eq!(
+        config.get_envs().get("CONFIG_DIR").unwrap(),
+        &expected.to_string_lossy()
+    );
+}
@@ -0,0 +1,144 @@
+use crate::tools::fixture_path;
+use awsx::config::Config;
+
+#[test]
+fn expressions_are_evaluated_once() {
+    let path = fixture_path("expressions/config.toml");
+    let config = Config::from_path(path, Default::default()).unwrap();
+
+    let first = config.get_envs();
+    let second = config.get_envs();
+
+    assert_eq!(first.get("RANDOM_NUMBER"), second.get("RANDOM_NUMBER"));
+}
+
+#[test]
+fn expressions_are_evaluated_again_after_modification() {
+    let path = fixture_path("expressions/config.toml");
+    let mut config = Config::from_path(path, Default::default()).unwrap();
+
+    assert_eq!(config.get_envs().get("BASE").unwrap(), "static-a");
+
+    config.set_string("env.STATIC", "modified");
+
+    assert_eq!(config.get_envs().get("BASE").unwrap(), "modified-a");
+}
+
+#[test]
+fn expressions_are_evaluated_in_dependency_order() {
+    let path = fixture_path("expressions/config.toml");
+    let config = Config::from_path(path, Default::default()).unwrap();
+
+    let envs = config.get_envs();
+
+    assert_eq!(envs.get("BASE").unwrap(), "static-a");
+    assert_eq!(envs.get("INTERMEDIATE").unwrap(), "static-a-b");
+    assert_eq!(envs.get("DEPENDENT").unwrap(), "static-a-b-c");
+}
+
+#[test]
+fn cyclic_expressions() {
+    let mut config = Config::new();
+    config.set_string("env.A", "{{ echo $B }}");
+    config.set_string("env.B", "{{ echo $A }}");
+
+    let r = config.try_get_envs();
+
+    assert!(r.is_err());
+    assert!(r.unwrap_err().to_string().contains("cyclic dependency"));
+}
+
+#[test]
+fn interpreter_per_file_and_per_value() {
+    let path = fixture_path("sandbox/config.toml");
+    let config = Config::from_path(path, Default::default()).unwrap();
+
+    let envs = config.get_envs();
+
+    assert_eq!(envs.get("PYTHON").unwrap(), "42");
+    assert_eq!(envs.get("SHELL").unwrap(), "yes-unset");
+}
+
+#[test]
+fn expressions_can_be_disabled() {
+    let path = fixture_path("sandbox/config.toml");
+    let mut config = Config::from_path(path, Default::default()).unwrap();
+    config
+        .set_from_args(["expressions.interpreter=none"])
+        .unwrap();
+
+    let r = config.try_get_envs();
+
+    assert!(r.is_err());
+    assert!(r
+        .unwrap_err()
+        .to_string()
+        .contains("Expressions are disabled"));
+}
+
+#[test]
+fn expressions_time_out() {
+    let path = fixture_path("sandbox/config.toml");
+    let mut config = Config::from_path(path, Default::default()).unwrap();
+    config.set_float("expressions.timeout", 0.1);
+    config.set_string("env.PYTHON", "{{ #!sh sleep 5 }}");
+
+    let r = config.try_get_envs();
+
+    assert!(r.is_err());
+    assert!(r.unwrap_err().to_string().contains("timed out"));
+}
+
+#[test]
+fn expressions_only_call_allowed_commands() {
+    let path = fixture_path("sandbox/config.toml");
+    let mut config = Config::from_path(path, Default::default()).unwrap();
+    config
+        .set_from_args([r#"expressions.allowed_commands=["echo"]"#])
+        .unwrap();
+    config.set_string("env.PYTHON", "{{ #!bash echo $(curl example.com) }}");
+
+    let r = config.try_get_envs();
+
+    assert!(r.is_err());
+    assert!(r
+        .unwrap_err()
+        .to_string()
+        .contains("Command \"curl\" is not allowed"));
+}
+
+#[test]
+fn expressions_can_not_call_variables() {
+    let path = fixture_path("sandbox/config.toml");
+    let mut config = Config::from_path(path, Default::default()).unwrap();
+    config
+        .set_from_args([r#"expressions.allowed_commands=["echo", "$a"]"#])
+        .unwrap();
+    config.set_string("env.PYTHON", "{{ #!bash a=rm; $a -rf x }}");
+
+    let r = config.try_get_envs();
+
+    assert!(r.is_err());
+    assert!(r
+        .unwrap_err()
+        .to_string()
+        .contains("Command \"$a\" is not allowed"));
+}
+
+#[test]
+fn expressions_can_not_hide_commands_behind_redirections() {
+    let path = fixture_path("sandbox/config.toml");
+    let mut config = Config::from_path(path, Default::default()).unwrap();
+    config
+        .set_from_args([r#"expressions.allowed_commands=["echo"]"#])
+        .unwrap();
+    config.set_string("env.PYTHON", "{{ #!bash >/dev/null rm -rf x }}");
+
+    let r = config.try_get_envs();
+
+    assert!(r.is_err());
+    assert!(r
+        .unwrap_err()
+        .to_string()
+        .contains("Command \"rm\" is not allowed"));
+}
@@ -0,0 +1,79 @@
+use crate::tools::fixture_path;
+use awsx::config::{Config, Error, Options};
+use toml::Value;
+
+#[test]
+fn loads_all_formats_in_the_same_directory() {
+    let path = fixture_path("formats/config.toml");
+    let config = Config::from_path(path, Default::default()).unwrap();
+
+    assert_eq!(config.get_string("env.AWS_PROFILE").unwrap(), "toml");
+    assert_eq!(
+        config.get_string("env.AWS_DEFAULT_REGION").unwrap(),
+        "eu-central-1"
+    );
+    assert_eq!(config.get_string("parameters.ImageTag").unwrap(), "latest");
+    assert_eq!(config.get_string("yaml_only").unwrap(), "yaml");
+    assert_eq!(config.get_string("json_only").unwrap(), "json");
+
+    let (_, filepath) = config.get_with_filepath("yaml_only").unwrap();
+    assert_eq!(
+        filepath,
+        fixture_path("formats/config.yaml").canonicalize().unwrap()
+    );
+}
+
+#[test]
+fn converts_yaml_values() {
+    let path = fixture_path("formats/config.yaml");
+    let options = Options {
+        nested: false,
+        ..Default::default()
+    };
+    let config = Config::from_path(path, options).unwrap();
+
+    assert_eq!(
+        config.get_array("parameters.Ports").unwrap(),
+        &vec![Value::Integer(80), Value::Integer(443)]
+    );
+}
+
+#[test]
+fn converts_json_values() {
+    let path = fixture_path("formats/config.json");
+    let options = Options {
+        nested: false,
+        ..Default::default()
+    };
+    let config = Config::from_path(path, options).unwrap();
+
+    assert_eq!(config.get_int("parameters.Count"), Some(&3));
+    assert_eq!(config.get_float("parameters.Ratio"), Some(&0.5));
+    assert_eq!(config.get_bool("parameters.Enabled"), Some(&true));
+    assert_eq!(config.get("parameters.Empty"), None);
+}
+
+#[test]
+fn restricts_filenames() {
+    let path = fixture_path("formats/config.toml");
+    let options = Options {
+        filenames: vec!["config.json".to_string()],
+        ..Default::default()
+    };
+    let config = Config::from_path(path, options).unwrap();
+
+    assert_eq!(config.get_string("env.AWS_PROFILE").unwrap(), "json");
+    assert_eq!(config.get("yaml_only"), None);
+}
+
+#[test]
+fn rejects_yaml_without_mapping() {
+    let path = fixture_path("formats/invalid/config.yaml");
+    let options = Options {
+        nested: false,
+        ..Default::default()
+    };
+    let r = Config::from_path(path, options);
+
+    assert!(matches!(r, Err(Error::Load { .. })));
+}
@@ -0,0 +1,54 @@
+use crate::tools::fixture_path;
+use awsx::config::{Config, Error, Options};
+
+fn options() -> Options {
+    Options {
+        nested: false,
+        ..Default::default()
+    }
+}
+
+#[test]
+fn included_files_rank_below_the_including_file() {
+    let path = fixture_path("includes/project/config.toml");
+    let config = Config::from_path(path, options()).unwrap();
+
+    assert_eq!(config.get_string("vpc_id").unwrap(), "vpc-project");
+    assert_eq!(config.get_string("subnet_id").unwrap(), "subnet-account");
+    assert_eq!(config.get_string("account_id").unwrap(), "123456789012");
+    assert_eq!(config.get_string("env.AWS_PROFILE").unwrap(), "default");
+    assert_eq!(
+        config.get_string("env.AWS_DEFAULT_REGION").unwrap(),
+        "eu-west-1"
+    );
+}
+
+#[test]
+fn reports_included_file_as_provenance() {
+    let path = fixture_path("includes/project/config.toml");
+    let config = Config::from_path(path, options()).unwrap();
+
+    let (_, filepath) = config.get_with_filepath("env.AWS_DEFAULT_REGION").unwrap();
+    assert_eq!(
+        filepath,
+        fixture_path("includes/shared/networking.toml")
+            .canonicalize()
+            .unwrap()
+    );
+}
+
+#[test]
+fn cyclic_includes() {
+    let path = fixture_path("includes/cyclic/a.toml");
+    let r = Config::from_path(path, options());
+
+    assert!(matches!(r, Err(Error::Load { msg, .. }) if msg.contains("cyclic include")));
+}
+
+#[test]
+fn missing_include() {
+    let path = fixture_path("includes/missing/config.toml");
+    let r = Config::from_path(path, options());
+
+    assert!(matches!(r, Err(Error::Load { msg, .. }) if msg.contains("does_not_exist.toml")));
+}
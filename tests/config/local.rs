@@ -0,0 +1,51 @@
+use crate::tools::temp_fixture;
+use awsx::config::{Config, Options};
+
+#[test]
+fn local_file_ranks_above_its_sibling() {
+    let dir = temp_fixture("nested_configs", "local_file_ranks_above_its_sibling");
+    std::fs::write(
+        dir.join("sub/config.local.toml"),
+        "[env]\nAWS_PROFILE = \"personal\"\nKEYPAIR = \"my-key\"\n",
+    )
+    .unwrap();
+    let options = Options {
+        project_root: Some(dir.clone()),
+        ..Default::default()
+    };
+
+    let config = Config::from_path(dir.join("sub/config.toml"), options).unwrap();
+
+    assert_eq!(config.get_string("env.AWS_PROFILE").unwrap(), "personal");
+    assert_eq!(config.get_string("env.KEYPAIR").unwrap(), "my-key");
+    assert_eq!(config.get_string("var_a").unwrap(), "cba");
+
+    let (_, filepath) = config.get_with_filepath("env.AWS_PROFILE").unwrap();
+    assert_eq!(
+        filepath,
+        dir.join("sub/config.local.toml").canonicalize().unwrap()
+    );
+}
+
+#[test]
+fn local_file_of_outer_config_ranks_below_inner_config() {
+    let dir = temp_fixture(
+        "nested_configs",
+        "local_file_of_outer_config_ranks_below_inner_config",
+    );
+    std::fs::write(
+        dir.join("config.local.toml"),
+        "var_a = \"local\"\nvar_b = \"local\"\n",
+    )
+    .unwrap();
+    let options = Options {
+        project_root: Some(dir.clone()),
+        ..Default::default()
+    };
+
+    let config = Config::from_path(dir.join("sub/config.toml"), options).unwrap();
+
+    assert_eq!(config.get_string("var_a").unwrap(), "cba");
+    assert_eq!(config.get_string("var_b").unwrap(), "local");
+    assert_eq!(config.get_string("var_c").unwrap(), "ghi");
+}
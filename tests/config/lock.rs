@@ -0,0 +1,126 @@
+use crate::tools::temp_fixture;
+use awsx::config::{Config, Options};
+use std::path::Path;
+
+fn load(dir: &Path, refresh: bool) -> Config {
+    let options = Options {
+        nested: false,
+        refresh,
+        ..Default::default()
+    };
+    Config::from_path(dir.join("config.toml"), options).unwrap()
+}
+
+#[test]
+fn locked_values_are_used() {
+    let dir = temp_fixture("lock", "locked_values_are_used");
+    let mut config = load(&dir, false);
+
+    let lock_path = config.lock().unwrap();
+    let build = config.get_envs().get("BUILD").unwrap().to_owned();
+
+    assert_eq!(lock_path, dir.canonicalize().unwrap().join("awsx.lock"));
+    let contents = std::fs::read_to_string(&lock_path).unwrap();
+    assert!(contents.contains("dir = \".\""));
+    assert!(contents.contains(&format!("value = \"{}\"", build)));
+    assert!(contents.contains("expression = \"echo v$RANDOM$RANDOM$RANDOM\""));
+
+    let locked = load(&dir, false);
+    assert_eq!(locked.get_envs().get("BUILD").unwrap(), &build);
+
+    let refreshed = load(&dir, true);
+    assert_ne!(refreshed.get_envs().get("BUILD").unwrap(), &build);
+}
+
+#[test]
+fn locked_values_survive_modifications() {
+    let dir = temp_fixture("lock", "locked_values_survive_modifications");
+    let mut config = load(&dir, false);
+    config.lock().unwrap();
+    let build = config.get_envs().get("BUILD").unwrap().to_owned();
+
+    let mut config = load(&dir, false);
+    config.set_string("env.OTHER", "value");
+
+    assert_eq!(config.get_envs().get("BUILD").unwrap(), &build);
+}
+
+#[test]
+fn only_resolved_values_are_locked() {
+    let dir = temp_fixture("lock", "only_resolved_values_are_locked");
+    std::fs::write(
+        dir.join("config.toml"),
+        r#"
+[parameters]
+ImageTag = '{{ echo default }}'
+
+[environments.staging]
+var_a = '{{ echo staging-var }}'
+
+[environments.staging.parameters]
+ImageTag = '{{ echo staging }}'
+
+[environments.production.parameters]
+ImageTag = '{{ echo production }}'
+"#,
+    )
+    .unwrap();
+    let options = Options {
+        nested: false,
+        environment: Some("staging".to_string()),
+        ..Default::default()
+    };
+    let mut config = Config::from_path(dir.join("config.toml"), options).unwrap();
+
+    // no AWS_PROFILE or AWS_DEFAULT_REGION are needed
+    let lock_path = config.lock().unwrap();
+
+    let contents = std::fs::read_to_string(lock_path).unwrap();
+    assert!(contents.contains("expression = \"echo staging\""));
+    assert!(contents.contains("expression = \"echo staging-var\""));
+    assert!(!contents.contains("echo production"));
+    assert!(!contents.contains("echo default"));
+}
+
+#[test]
+fn locked_values_are_used_per_environment() {
+    let dir = temp_fixture("lock", "locked_values_are_used_per_environment");
+    std::fs::write(
+        dir.join("config.toml"),
+        r#"
+[env]
+BUILD = '{{ echo "$STAGE-$RANDOM$RANDOM" }}'
+
+[environments.staging.env]
+STAGE = "staging"
+
+[environments.production.env]
+STAGE = "production"
+"#,
+    )
+    .unwrap();
+    let load = |environment: &str| {
+        let options = Options {
+            nested: false,
+            environment: Some(environment.to_string()),
+            ..Default::default()
+        };
+        Config::from_path(dir.join("config.toml"), options).unwrap()
+    };
+
+    let mut staging = load("staging");
+    staging.lock().unwrap();
+    let build = staging.get_envs().get("BUILD").unwrap().to_owned();
+    assert!(build.starts_with("staging-"));
+
+    assert_eq!(load("staging").get_envs().get("BUILD").unwrap(), &build);
+    let production = load("production")
+        .get_envs()
+        .get("BUILD")
+        .unwrap()
+        .to_owned();
+    assert!(production.starts_with("production-"));
+
+    let contents = std::fs::read_to_string(dir.join("awsx.lock")).unwrap();
+    assert!(contents.contains("inputs = \""));
+}
@@ -0,0 +1,138 @@
+use crate::tools::fixture_path;
+use awsx::config::{Config, Error};
+use toml::Value;
+
+fn names(config: &Config) -> Vec<(&str, Option<&str>)> {
+    config
+        .get_array("array")
+        .unwrap()
+        .iter()
+        .map(|t| {
+            (
+                t.get("name").unwrap().as_str().unwrap(),
+                t.get("b").map(|b| b.as_str().unwrap()),
+            )
+        })
+        .collect()
+}
+
+#[test]
+fn merges_nested_tables() {
+    let path = fixture_path("nested_configs/sub/config.toml");
+    let config = Config::from_path(path, Default::default()).unwrap();
+
+    let sub = config.get_table("sub").unwrap();
+    assert_eq!(sub.get("var_a").unwrap().as_str(), Some("xyz"));
+    assert_eq!(
+        sub.get("a").unwrap().get("var_b").unwrap().as_str(),
+        Some("uvw")
+    );
+    assert_eq!(
+        sub.get("b").unwrap().get("var_c").unwrap().as_str(),
+        Some("sph")
+    );
+
+    let env = config.get_table("env").unwrap();
+    assert_eq!(env.get("AWS_PROFILE").unwrap().as_str(), Some("edited"));
+}
+
+#[test]
+fn reports_layer_of_merged_values() {
+    let path = fixture_path("nested_configs/sub/config.toml");
+    let config = Config::from_path(path, Default::default()).unwrap();
+
+    let (_, filepath) = config.get_with_filepath("sub.a.var_b").unwrap();
+    assert_eq!(
+        filepath,
+        fixture_path("nested_configs/config.toml")
+            .canonicalize()
+            .unwrap()
+    );
+
+    let (_, filepath) = config.get_with_filepath("sub").unwrap();
+    assert_eq!(
+        filepath,
+        fixture_path("nested_configs/sub/config.toml")
+            .canonicalize()
+            .unwrap()
+    );
+}
+
+#[test]
+fn replaces_arrays_by_default() {
+    let path = fixture_path("nested_configs/sub/config.toml");
+    let config = Config::from_path(path, Default::default()).unwrap();
+
+    assert_eq!(
+        names(&config),
+        vec![("second", Some("edited")), ("third", None)]
+    );
+    assert_eq!(
+        config.get_array("list.values").unwrap(),
+        &vec![Value::String("c".to_string())]
+    );
+}
+
+#[test]
+fn appends_arrays() {
+    let path = fixture_path("nested_configs/sub/config.toml");
+    let mut config = Config::from_path(path, Default::default()).unwrap();
+    config.set_string("merge.arrays.default", "append");
+
+    assert_eq!(
+        names(&config),
+        vec![
+            ("first", None),
+            ("second", Some("uvw")),
+            ("second", Some("edited")),
+            ("third", None)
+        ]
+    );
+    assert_eq!(config.get_array("list.values").unwrap().len(), 3);
+}
+
+#[test]
+fn merges_arrays_of_tables_by_key() {
+    let path = fixture_path("nested_configs/sub/config.toml");
+    let mut config = Config::from_path(path, Default::default()).unwrap();
+    let mut strategy = toml::value::Map::new();
+    strategy.insert("by_key".to_string(), Value::String("name".to_string()));
+    config.set_table("merge.arrays.array", strategy);
+
+    assert_eq!(
+        names(&config),
+        vec![("first", None), ("second", Some("edited")), ("third", None)]
+    );
+    assert_eq!(
+        config.get_array("array").unwrap()[0]
+            .get("a")
+            .unwrap()
+            .as_str(),
+        Some("xyz")
+    );
+    assert_eq!(config.get_array("list.values").unwrap().len(), 1);
+}
+
+#[test]
+fn rejects_invalid_array_strategy_on_load() {
+    let r = Config::from_path(
+        fixture_path("invalid_merge/config.toml"),
+        Default::default(),
+    );
+
+    assert!(matches!(r, Err(Error::Load { .. })));
+}
+
+#[test]
+fn reports_contributing_layers() {
+    let path = fixture_path("nested_configs/sub/config.toml");
+    let mut config = Config::from_path(path, Default::default()).unwrap();
+
+    assert_eq!(config.contributing_layers("env.AWS_PROFILE"), 1);
+    assert_eq!(config.contributing_layers("sub"), 2);
+    assert_eq!(config.contributing_layers("array"), 1);
+    assert_eq!(config.contributing_layers("non_existent"), 0);
+
+    config.set_string("merge.arrays.default", "append");
+    assert_eq!(config.contributing_layers("array"), 2);
+}
@@ -1,7 +1,23 @@
 use crate::tools::fixture_path;
 use awsx::config::{Config, Options};
 
+mod cli;
+mod diff;
+mod dotenv;
+mod environments;
+mod expressions;
+mod formats;
+mod includes;
+mod local;
+mod lock;
+mod merge;
 mod options;
+mod overrides;
+mod persist;
+mod remote;
+mod schema;
+mod secrets;
+mod typed;
 
 #[test]
 fn get_exact_config_values() {
@@ -162,6 +178,54 @@ fn load_nested_configs_with_overrides() {
     assert_eq!(unpack_str(config.get("sub.c.var_a")), Some("sub_c_aaa"));
 }
 
+#[test]
+fn load_multiple_paths() {
+    let options = Options {
+        nested: false,
+        ..Default::default()
+    };
+    let config = Config::from_paths(
+        [
+            fixture_path("composed/base.toml"),
+            fixture_path("composed/region.toml"),
+            fixture_path("composed/stage"),
+        ],
+        options,
+    )
+    .unwrap();
+
+    assert_eq!(unpack_str(config.get("env.AWS_PROFILE")), Some("base"));
+    assert_eq!(
+        unpack_str(config.get("env.AWS_DEFAULT_REGION")),
+        Some("eu-west-3")
+    );
+    assert_eq!(
+        unpack_str(config.get("parameters.InstanceType")),
+        Some("m5.large")
+    );
+}
+
+#[test]
+fn load_multiple_paths_with_nested_configs() {
+    let config = Config::from_paths(
+        [
+            fixture_path("composed/region.toml"),
+            fixture_path("nested_configs/sub"),
+        ],
+        Default::default(),
+    )
+    .unwrap();
+
+    // explicit paths rank above the config files of their parent directories
+    assert_eq!(unpack_str(config.get("env.AWS_PROFILE")), Some("edited"));
+    assert_eq!(
+        unpack_str(config.get("env.AWS_DEFAULT_REGION")),
+        Some("eu-central-1")
+    );
+    assert_eq!(unpack_str(config.get("var_b")), Some("region"));
+    assert_eq!(unpack_str(config.get("var_c")), Some("ghi"));
+}
+
 fn unpack_str(key: Option<&toml::Value>) -> Option<&str> {
     if let Some(v) = key {
         match v {
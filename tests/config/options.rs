@@ -1,26 +1,19 @@
+use crate::tools::temp_fixture;
 use awsx::config::Options;
 use std::path::PathBuf;
 
+fn temp_project(test_name: &str) -> PathBuf {
+    temp_fixture("project", test_name).canonicalize().unwrap()
+}
+
 #[test]
-// TODO:
-// This test does not work when the .git dir is not where the cargo.toml file is,
-// for example when the repo is being used as a submodule in another repository
-#[ignore]
 fn get_project_root_automatically() {
-    let options = Options {
-        project_root: None,
-        ..Default::default()
-    };
+    let dir = temp_project("get_project_root_automatically");
+    std::fs::write(dir.join(".awsx-root"), "").unwrap();
 
-    let root_dir = PathBuf::from(
-        std::env::var("CARGO_MANIFEST_DIR").expect("CARGO_MANIFEST_DIR should be set"),
-    );
+    let root = Options::find_project_root(dir.join("sub/inner")).unwrap();
 
-    let r = options.get_project_root();
-    match r {
-        Ok(path) => assert_eq!(path, root_dir.canonicalize().expect("valid path")),
-        Err(_) => unreachable!(),
-    }
+    assert_eq!(root, dir);
 }
 
 #[test]
@@ -80,3 +73,35 @@ fn get_project_root_with_absolute_path() {
         Err(_) => unreachable!(),
     }
 }
+
+#[test]
+fn find_project_root_with_git_file() {
+    let dir = temp_project("find_project_root_with_git_file");
+    std::fs::write(dir.join("sub/.git"), "gitdir: ../.git/modules/sub").unwrap();
+
+    let root = Options::find_project_root(dir.join("sub/inner")).unwrap();
+
+    assert_eq!(root, dir.join("sub"));
+}
+
+#[test]
+fn find_project_root_with_marker() {
+    let dir = temp_project("find_project_root_with_marker");
+    std::fs::create_dir(dir.join(".git")).unwrap();
+    std::fs::write(dir.join("sub/.awsx-root"), "").unwrap();
+
+    let root = Options::find_project_root(dir.join("sub/inner")).unwrap();
+
+    assert_eq!(root, dir.join("sub"));
+}
+
+#[test]
+fn find_project_root_stops_at_the_closest_marker() {
+    let dir = temp_project("find_project_root_stops_at_the_closest_marker");
+    std::fs::write(dir.join(".awsx-root"), "").unwrap();
+    std::fs::create_dir(dir.join("sub/.git")).unwrap();
+
+    let root = Options::find_project_root(dir.join("sub/inner")).unwrap();
+
+    assert_eq!(root, dir.join("sub"));
+}
@@ -0,0 +1,153 @@
+use crate::tools::fixture_path;
+use awsx::config::{Config, Error};
+use toml::Value;
+
+fn load() -> Config {
+    let path = fixture_path("environments/config.toml");
+    Config::from_path(path, Default::default()).unwrap()
+}
+
+#[test]
+fn set_from_args() {
+    let mut config = load();
+
+    config
+        .set_from_args(["parameters.ImageTag=v1.2.3", "count=3", "flag = true"])
+        .unwrap();
+
+    assert_eq!(config.get_string("parameters.ImageTag").unwrap(), "v1.2.3");
+    assert_eq!(config.get("count"), Some(&Value::Integer(3)));
+    assert_eq!(config.get("flag"), Some(&Value::Boolean(true)));
+    assert_eq!(
+        config.get_string("parameters.InstanceType").unwrap(),
+        "t3.nano"
+    );
+}
+
+#[test]
+fn set_from_invalid_args() {
+    let mut config = load();
+
+    let r = config.set_from_args(["missing_value"]);
+    assert!(matches!(r, Err(Error::InvalidOverride { arg }) if arg == "missing_value"));
+
+    let r = config.set_from_args(["=value"]);
+    assert!(matches!(r, Err(Error::InvalidOverride { .. })));
+}
+
+#[test]
+fn set_from_env_vars() {
+    let mut config = load();
+
+    config
+        .set_from_env_vars([
+            ("AWSX__PARAMETERS__ImageTag".to_string(), "v2".to_string()),
+            ("AWSX__ENV__AWS_PROFILE".to_string(), "ci".to_string()),
+            ("AWSX__CMD__SILENT".to_string(), "true".to_string()),
+            ("AWSX_ENV".to_string(), "staging".to_string()),
+            ("AWSX__INVALID__".to_string(), "ignored".to_string()),
+            ("UNRELATED".to_string(), "ignored".to_string()),
+        ])
+        .unwrap();
+
+    assert_eq!(config.get_string("parameters.ImageTag").unwrap(), "v2");
+    assert_eq!(config.get_string("env.AWS_PROFILE").unwrap(), "ci");
+    assert_eq!(config.get_bool("cmd.SILENT"), Some(&true));
+    assert_eq!(config.get("invalid"), None);
+    assert_eq!(config.get_envs().get("AWS_PROFILE").unwrap(), "ci");
+}
+
+#[test]
+fn args_take_precedence_over_env_vars() {
+    let mut config = load();
+
+    config
+        .set_from_env_vars([("AWSX__ENV__AWS_PROFILE".to_string(), "ci".to_string())])
+        .unwrap();
+    config.set_from_args(["env.AWS_PROFILE=cli"]).unwrap();
+
+    assert_eq!(config.get_string("env.AWS_PROFILE").unwrap(), "cli");
+}
+
+#[test]
+fn reports_override_source() {
+    let mut config = load();
+
+    config
+        .set_from_env_vars([("AWSX__PARAMETERS__ImageTag".to_string(), "v2".to_string())])
+        .unwrap();
+    config.set_from_args(["env.AWS_PROFILE=cli"]).unwrap();
+
+    let (_, filepath) = config.get_with_filepath("parameters.ImageTag").unwrap();
+    assert_eq!(filepath, Config::override_path());
+    assert_eq!(
+        config.override_source("parameters.ImageTag"),
+        Some("AWSX__PARAMETERS__ImageTag")
+    );
+    assert_eq!(
+        config.override_source("env.AWS_PROFILE"),
+        Some("--set env.AWS_PROFILE")
+    );
+
+    config.set_string("env.AWS_PROFILE", "direct");
+    assert_eq!(config.override_source("env.AWS_PROFILE"), None);
+}
+
+#[test]
+fn nested_args_replace_values() {
+    let mut config = load();
+
+    config.set_from_args(["count=3", "count.a.b=1"]).unwrap();
+
+    assert_eq!(config.get("count.a.b"), Some(&Value::Integer(1)));
+
+    config
+        .set_from_env_vars([("AWSX__FLAG".to_string(), "x".to_string())])
+        .unwrap();
+    config.set_from_args(["flag.A=y"]).unwrap();
+
+    assert_eq!(config.get_string("flag.A").unwrap(), "y");
+}
+
+#[test]
+fn env_overrides_are_strings() {
+    let mut config = load();
+
+    config
+        .set_from_env_vars([("AWSX__ENV__DEBUG".to_string(), "true".to_string())])
+        .unwrap();
+    config
+        .set_from_args(["env.AWS_ACCOUNT_ID=123456789012", "count=3"])
+        .unwrap();
+
+    let envs = config.try_get_envs().unwrap();
+    assert_eq!(envs.get("DEBUG").unwrap(), "true");
+    assert_eq!(envs.get("AWS_ACCOUNT_ID").unwrap(), "123456789012");
+    assert_eq!(config.get("count"), Some(&Value::Integer(3)));
+}
+
+#[test]
+fn non_string_env_values_are_errors() {
+    let mut config = load();
+
+    config.set_int("env.COUNT", 3);
+
+    let r = config.try_get_envs();
+    assert!(matches!(r, Err(Error::Deserialize { key, .. }) if key == "env.COUNT"));
+}
+
+#[test]
+fn invalid_merge_settings_are_errors() {
+    let mut config = load();
+
+    let r = config.set_from_args(["merge.arrays.default=bogus"]);
+    assert!(matches!(r, Err(Error::Load { .. })));
+
+    let mut config = load();
+
+    let r = config.set_from_env_vars([(
+        "AWSX__MERGE__ARRAYS__DEFAULT".to_string(),
+        "bogus".to_string(),
+    )]);
+    assert!(matches!(r, Err(Error::Load { .. })));
+}
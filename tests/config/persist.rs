@@ -0,0 +1,56 @@
+use crate::tools::temp_fixture;
+use awsx::config::{Config, Options};
+use toml::Value;
+
+#[test]
+fn preserves_formatting() {
+    let dir = temp_fixture("persist", "preserves_formatting");
+    let path = dir.join("config.toml");
+    let options = Options {
+        nested: false,
+        ..Default::default()
+    };
+    let mut config = Config::from_path(&path, options).unwrap();
+
+    config
+        .set_in_file(
+            "parameters.ImageId",
+            Value::String("ami-12345678".into()),
+            &path,
+        )
+        .unwrap();
+    config
+        .set_in_file(
+            "parameters.Exposed.value",
+            Value::String("def".into()),
+            &path,
+        )
+        .unwrap();
+    config
+        .set_in_file("outputs.instance.id", Value::String("i-123".into()), &path)
+        .unwrap();
+
+    let expected = r#"# Settings for the image pipeline
+var_a = "abc" # trailing comment
+
+[env]
+AWS_PROFILE = "default"
+AWS_DEFAULT_REGION = "eu-central-1"
+
+# Parameters passed to the stack
+[parameters]
+ImageId = "ami-12345678" # replaced by the pipeline
+InstanceType = "t3.nano"
+Exposed = { value = "def", expose = true }
+
+[outputs.instance]
+id = "i-123"
+"#;
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), expected);
+
+    assert_eq!(
+        config.get_string("parameters.ImageId").unwrap(),
+        "ami-12345678"
+    );
+    assert_eq!(config.get_string("outputs.instance.id").unwrap(), "i-123");
+}
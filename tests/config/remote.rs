@@ -0,0 +1,105 @@
+use crate::tools::{fake_aws, fixture_path, temp_fixture};
+use awsx::config::{Config, Options};
+use std::path::PathBuf;
+
+#[test]
+fn remote_layers_from_cache() {
+    let options = Options {
+        nested: false,
+        offline: true,
+        cache_dir: Some(fixture_path("remote/cache")),
+        ..Default::default()
+    };
+    let config = Config::from_path(fixture_path("remote/config.toml"), options).unwrap();
+
+    let envs = config.get_envs();
+    assert_eq!(envs.get("AWS_PROFILE").unwrap(), "project");
+    assert_eq!(envs.get("AWS_DEFAULT_REGION").unwrap(), "eu-central-1");
+    assert_eq!(envs.get("VPC_ID").unwrap(), "vpc-123");
+
+    let (_, source) = config.get_with_filepath("env.AWS_DEFAULT_REGION").unwrap();
+    assert_eq!(source, PathBuf::from("ssm:/team/shared/"));
+    let (_, source) = config.get_with_filepath("env.VPC_ID").unwrap();
+    assert_eq!(source, PathBuf::from("s3://bucket/awsx/shared.toml"));
+}
+
+#[test]
+fn remote_layers_without_cache() {
+    let options = Options {
+        nested: false,
+        offline: true,
+        cache_dir: Some(fixture_path("remote/missing")),
+        ..Default::default()
+    };
+
+    let error = Config::from_path(fixture_path("remote/config.toml"), options).unwrap_err();
+
+    assert!(error.to_string().contains("not cached"));
+}
+
+/// Writes a config next to a fake `aws` that serves the S3 layer and the SSM parameters of the
+/// fixture, or prints nothing with `empty`
+fn setup_fetch(test_name: &str, empty: bool) -> (PathBuf, Options) {
+    let dir = temp_fixture("remote", test_name);
+    let path = fake_aws(
+        &dir,
+        &format!(
+            r#"[ "{}" = "true" ] && exit 0
+case "$1" in
+  s3) printf '[env]\nVPC_ID = "vpc-456"\n' ;;
+  ssm) echo '{{"Parameters": [{{"Name": "/team/shared/env/AWS_DEFAULT_REGION", "Value": "eu-north-1"}}]}}' ;;
+esac
+"#,
+            empty
+        ),
+    );
+
+    let config = std::fs::read_to_string(dir.join("config.toml")).unwrap();
+    std::fs::write(
+        dir.join("config.toml"),
+        format!("{}PATH = {:?}\n\n[cmd]\nsilent = true\n", config, path),
+    )
+    .unwrap();
+
+    let options = Options {
+        nested: false,
+        cache_dir: Some(dir.join("cache")),
+        ..Default::default()
+    };
+
+    (dir, options)
+}
+
+#[test]
+fn remote_layers_are_fetched_and_cached_privately() {
+    let (dir, options) = setup_fetch("remote_layers_are_fetched_and_cached_privately", false);
+
+    let config = Config::from_path(dir.join("config.toml"), options).unwrap();
+
+    let envs = config.get_envs();
+    assert_eq!(envs.get("VPC_ID").unwrap(), "vpc-456");
+    assert_eq!(envs.get("AWS_DEFAULT_REGION").unwrap(), "eu-north-1");
+
+    let cache = dir.join("cache/remote/ssm_3a_2fteam_2fshared_2f.toml");
+    assert!(std::fs::read_to_string(&cache)
+        .unwrap()
+        .contains("eu-north-1"));
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let mode = std::fs::metadata(&cache).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+    }
+}
+
+#[test]
+fn empty_remote_layers_use_the_cache() {
+    let (dir, options) = setup_fetch("empty_remote_layers_use_the_cache", true);
+    let cache = dir.join("cache/remote/s3_3a_2f_2fbucket_2fawsx_2fshared_2etoml.toml");
+    let cached = std::fs::read_to_string(&cache).unwrap();
+
+    let config = Config::from_path(dir.join("config.toml"), options).unwrap();
+
+    assert_eq!(config.get_envs().get("VPC_ID").unwrap(), "vpc-123");
+    assert_eq!(std::fs::read_to_string(&cache).unwrap(), cached);
+}
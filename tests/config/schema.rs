@@ -0,0 +1,66 @@
+use crate::tools::fixture_path;
+use awsx::config::{Config, Error};
+use serde_json::json;
+
+#[test]
+fn builtin_keys() {
+    let config = Config::new();
+
+    let schema = config.schema().unwrap();
+
+    assert_eq!(
+        schema.pointer("/properties/cmd/properties/silent/type"),
+        Some(&json!("boolean"))
+    );
+    assert_eq!(
+        schema.pointer("/properties/expressions/properties/interpreter/enum"),
+        Some(&json!(["bash", "sh", "python3", "none"]))
+    );
+    assert!(schema
+        .pointer("/properties/env/additionalProperties")
+        .is_some());
+
+    let list = |pointer: &str| {
+        schema
+            .pointer(pointer)
+            .and_then(|v| v.as_array())
+            .unwrap()
+            .iter()
+            .any(|v| v.get("type") == Some(&json!("array")))
+    };
+    assert!(list("/properties/parameters/additionalProperties/oneOf"));
+    assert!(list(
+        "/properties/parameters/additionalProperties/oneOf/3/properties/value/oneOf"
+    ));
+}
+
+#[test]
+fn declared_keys() {
+    let path = fixture_path("schema/config.toml");
+    let config = Config::from_path(path, Default::default()).unwrap();
+
+    let schema = config.schema().unwrap();
+
+    assert_eq!(
+        schema.pointer("/properties/app"),
+        Some(&json!({ "type": "object", "properties": { "port": { "type": "integer" } } }))
+    );
+    assert_eq!(
+        schema.pointer("/properties/parameters/properties/InstanceType"),
+        Some(&json!({ "type": "string", "enum": ["t3.micro", "t3.small"] }))
+    );
+    assert_eq!(
+        schema.pointer("/properties/env/properties/AWS_PROFILE"),
+        Some(&json!({ "type": "string", "description": "Profile of the shared account" }))
+    );
+}
+
+#[test]
+fn invalid_declaration() {
+    let mut config = Config::new();
+    config.set_int("schema.port", 1);
+
+    let error = config.schema().unwrap_err();
+
+    assert!(matches!(error, Error::InvalidSchema { key, .. } if key == "port"));
+}
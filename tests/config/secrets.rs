@@ -0,0 +1,183 @@
+use crate::tools::{fake_aws, temp_fixture};
+use age::{secrecy::ExposeSecret, x25519::Identity};
+use awsx::config::{Config, Error, Options};
+use std::path::{Path, PathBuf};
+
+fn setup(test_name: &str) -> (PathBuf, Config) {
+    let dir = temp_fixture("secrets", test_name);
+    write_identity(&dir.join("age.key"));
+
+    let options = Options {
+        nested: false,
+        ..Default::default()
+    };
+    let config = Config::from_path(dir.join("config.toml"), options).unwrap();
+
+    (dir, config)
+}
+
+fn write_identity(path: &Path) {
+    let identity = Identity::generate();
+    std::fs::write(path, identity.to_string().expose_secret()).unwrap();
+}
+
+fn reload(dir: &Path) -> Config {
+    let options = Options {
+        nested: false,
+        ..Default::default()
+    };
+    Config::from_path(dir.join("config.toml"), options).unwrap()
+}
+
+#[test]
+fn encrypted_values_are_decrypted() {
+    let (dir, mut config) = setup("encrypted_values_are_decrypted");
+    let path = dir.join("config.toml");
+
+    let encrypted = config.encrypt("env.PASSWORD", "hunter2", None).unwrap();
+    config
+        .set_in_file("env.PASSWORD", encrypted, &path)
+        .unwrap();
+    let encrypted = config
+        .encrypt("parameters.Token.value", "abc", None)
+        .unwrap();
+    config
+        .set_in_file("parameters.Token.value", encrypted, &path)
+        .unwrap();
+
+    let written = std::fs::read_to_string(&path).unwrap();
+    assert!(written.contains("PASSWORD = { encrypted = \""));
+    assert!(written.contains("# database password"));
+    assert!(!written.contains("hunter2"));
+
+    let envs = reload(&dir).get_envs();
+    assert_eq!(envs.get("PASSWORD").unwrap(), "hunter2");
+    assert_eq!(envs.get("AWSX_PARAMETER_TOKEN").unwrap(), "abc");
+}
+
+#[test]
+fn plain_values_are_not_decrypted() {
+    let (_, config) = setup("plain_values_are_not_decrypted");
+
+    let value = config.get("env.PASSWORD").unwrap();
+
+    assert_eq!(config.decrypt("env.PASSWORD", value).unwrap(), None);
+}
+
+#[test]
+#[should_panic(expected = "could not decrypt the value of \"env.PASSWORD\"")]
+fn decrypting_with_another_key() {
+    let (dir, mut config) = setup("decrypting_with_another_key");
+    let path = dir.join("config.toml");
+
+    let encrypted = config.encrypt("env.PASSWORD", "hunter2", None).unwrap();
+    config
+        .set_in_file("env.PASSWORD", encrypted, &path)
+        .unwrap();
+    write_identity(&dir.join("age.key"));
+
+    reload(&dir).get_envs();
+}
+
+/// Puts a fake `aws` in front of the PATH of the config that answers `kms encrypt` and
+/// `kms decrypt`, records its arguments and stdin, and prints nothing for the key `empty`
+fn fake_kms(dir: &Path, config: &mut Config) {
+    let path = fake_aws(
+        dir,
+        r#"dir="$(dirname "$0")"
+echo "$@" > "$dir/args"
+[ "$4" = "empty" ] && exit 0
+case "$2" in
+  encrypt) cat > "$dir/input"; echo "Y2lwaGVy" ;;
+  decrypt) echo "aHVudGVyMg==" ;;
+esac
+"#,
+    );
+
+    config.set_string("env.PATH", path);
+    config.set_bool("cmd.silent", true);
+}
+
+#[test]
+fn kms_values_are_read_even_if_silent() {
+    let (dir, mut config) = setup("kms_values_are_read_even_if_silent");
+    fake_kms(&dir, &mut config);
+
+    let encrypted = config
+        .encrypt("env.PASSWORD", "hunter2", Some("key"))
+        .unwrap();
+    assert_eq!(
+        encrypted.get("encrypted").unwrap().as_str(),
+        Some("Y2lwaGVy")
+    );
+    assert_eq!(
+        config.decrypt("env.PASSWORD", &encrypted).unwrap(),
+        Some("hunter2".to_string())
+    );
+}
+
+#[test]
+fn kms_plaintext_is_not_passed_as_argument() {
+    let (dir, mut config) = setup("kms_plaintext_is_not_passed_as_argument");
+    fake_kms(&dir, &mut config);
+
+    config
+        .encrypt("env.PASSWORD", "hunter2", Some("key"))
+        .unwrap();
+
+    let args = std::fs::read_to_string(dir.join("bin/args")).unwrap();
+    assert!(!args.contains("hunter2"));
+    assert!(!args.contains("aHVudGVyMg=="));
+    let input = std::fs::read_to_string(dir.join("bin/input")).unwrap();
+    assert_eq!(input, "hunter2");
+}
+
+#[test]
+fn kms_without_output_is_an_error() {
+    let (dir, mut config) = setup("kms_without_output_is_an_error");
+    fake_kms(&dir, &mut config);
+
+    let encrypted = config.encrypt("env.PASSWORD", "hunter2", Some("empty"));
+    assert!(matches!(encrypted, Err(Error::Encrypt { .. })));
+
+    let mut value = toml::value::Map::new();
+    value.insert("encrypted".to_string(), "Y2lwaGVy".into());
+    value.insert("kms".to_string(), "empty".into());
+    let decrypted = config.decrypt("env.PASSWORD", &toml::Value::Table(value));
+    assert!(matches!(decrypted, Err(Error::Decrypt { .. })));
+}
+
+#[test]
+fn kms_arguments_are_not_run_by_a_shell() {
+    let (dir, mut config) = setup("kms_arguments_are_not_run_by_a_shell");
+    fake_kms(&dir, &mut config);
+    let marker = dir.join("injected");
+
+    let mut value = toml::value::Map::new();
+    value.insert(
+        "encrypted".to_string(),
+        format!("x' ; touch '{}' ; echo '", marker.display()).into(),
+    );
+    value.insert("kms".to_string(), "key".into());
+    config
+        .decrypt("env.PASSWORD", &toml::Value::Table(value))
+        .unwrap();
+
+    assert!(!marker.exists());
+}
+
+#[test]
+fn decrypting_without_identity_is_an_error() {
+    let (dir, mut config) = setup("decrypting_without_identity_is_an_error");
+    let path = dir.join("config.toml");
+
+    let encrypted = config.encrypt("env.PASSWORD", "hunter2", None).unwrap();
+    config
+        .set_in_file("env.PASSWORD", encrypted, &path)
+        .unwrap();
+    std::fs::remove_file(dir.join("age.key")).unwrap();
+
+    let envs = reload(&dir).try_get_envs();
+
+    assert!(matches!(envs, Err(Error::Decrypt { key, .. }) if key == "env.PASSWORD"));
+}
@@ -0,0 +1,99 @@
+use crate::tools::fixture_path;
+use awsx::config::{Config, Error};
+use serde::Deserialize;
+use std::collections::HashMap;
+
+#[derive(Debug, Deserialize, PartialEq)]
+struct Network {
+    vpc_id: String,
+    subnets: Vec<String>,
+    ports: HashMap<String, u16>,
+}
+
+#[derive(Debug, Deserialize, PartialEq)]
+struct PartialNetwork {
+    vpc_id: String,
+    subnets: Option<Vec<String>>,
+}
+
+#[derive(Debug, Deserialize)]
+#[allow(dead_code)]
+struct Invalid {
+    name: String,
+    count: u32,
+}
+
+fn load() -> Config {
+    let path = fixture_path("typed/sub/config.toml");
+    Config::from_path(path, Default::default()).unwrap()
+}
+
+#[test]
+fn merged_as_struct() {
+    let config = load();
+
+    let network: Network = config.merged_as("network").unwrap();
+
+    assert_eq!(network.vpc_id, "vpc-456");
+    assert_eq!(network.subnets, vec!["subnet-a", "subnet-b"]);
+    assert_eq!(
+        network.ports,
+        HashMap::from([
+            ("http".to_string(), 80),
+            ("https".to_string(), 443),
+            ("ssh".to_string(), 22)
+        ])
+    );
+}
+
+#[test]
+fn get_as_struct_ignores_lower_layers() {
+    let config = load();
+
+    let network: PartialNetwork = config.get_as("network").unwrap();
+    assert_eq!(
+        network,
+        PartialNetwork {
+            vpc_id: "vpc-456".to_string(),
+            subnets: None
+        }
+    );
+
+    let r = config.get_as::<Network>("network");
+    assert!(matches!(r, Err(Error::Deserialize { .. })));
+}
+
+#[test]
+fn get_as_primitive() {
+    let config = load();
+
+    let port: u16 = config.merged_as("network.ports.http").unwrap();
+    assert_eq!(port, 80);
+}
+
+#[test]
+fn error_names_offending_key_and_file() {
+    let config = load();
+
+    match config.merged_as::<Invalid>("invalid") {
+        Err(Error::Deserialize { key, path, .. }) => {
+            assert_eq!(key, "invalid.count");
+            assert_eq!(
+                path,
+                fixture_path("typed/config.toml")
+                    .canonicalize()
+                    .unwrap()
+                    .to_string_lossy()
+            );
+        }
+        r => unreachable!("unexpected result {:?}", r),
+    }
+}
+
+#[test]
+fn missing_key() {
+    let config = load();
+
+    let r = config.merged_as::<Network>("non_existent");
+    assert!(matches!(r, Err(Error::MissingKey { key }) if key == "non_existent"));
+}
@@ -23,28 +23,402 @@ mod util {
         }
     }
 
+    mod deploy_action {
+        use awsx::stack::util::{deploy_action, DeployAction};
+
+        #[test]
+        fn test() {
+            assert_eq!(deploy_action(None), DeployAction::Create);
+            assert_eq!(deploy_action(Some("CREATE_COMPLETE")), DeployAction::Update);
+            assert_eq!(
+                deploy_action(Some("UPDATE_ROLLBACK_COMPLETE")),
+                DeployAction::Update
+            );
+            assert_eq!(
+                deploy_action(Some("ROLLBACK_COMPLETE")),
+                DeployAction::Recreate
+            );
+            assert_eq!(
+                deploy_action(Some("REVIEW_IN_PROGRESS")),
+                DeployAction::Review
+            );
+            assert_eq!(
+                deploy_action(Some("UPDATE_IN_PROGRESS")),
+                DeployAction::Busy("UPDATE_IN_PROGRESS".to_string())
+            );
+            assert_eq!(
+                deploy_action(Some("UPDATE_ROLLBACK_FAILED")),
+                DeployAction::Blocked("UPDATE_ROLLBACK_FAILED".to_string())
+            );
+        }
+    }
+
+    mod render_change_set {
+        use crate::tools::fixture_path;
+        use awsx::stack::util::render_change_set;
+
+        #[test]
+        fn test() {
+            let contents = std::fs::read_to_string(fixture_path("change_set.json")).unwrap();
+            let change_set = serde_json::from_str(&contents).unwrap();
+
+            let expected = [
+                "+ Add     Bucket (AWS::S3::Bucket)",
+                "~ Modify  Instance (AWS::EC2::Instance) [replacement]",
+                "      Properties.ImageId, requires recreation: Always, DirectModification",
+                "      Properties.InstanceType, ParameterReference InstanceType",
+                "- Remove  Topic (AWS::SNS::Topic)",
+                "1 to add, 1 to modify, 1 to remove",
+            ]
+            .join("\n");
+
+            assert_eq!(render_change_set(&change_set), expected);
+        }
+
+        #[test]
+        fn without_changes() {
+            let change_set = serde_json::json!({ "Changes": [] });
+
+            assert_eq!(
+                render_change_set(&change_set),
+                "0 to add, 0 to modify, 0 to remove"
+            );
+        }
+    }
+
+    mod stack_events {
+        use crate::tools::fixture_path;
+        use awsx::stack::util::{operation_succeeded, root_causes, StackEvent};
+
+        fn events(fixture: &str) -> Vec<StackEvent> {
+            let contents = std::fs::read_to_string(fixture_path(fixture)).unwrap();
+            StackEvent::from_json(&serde_json::from_str(&contents).unwrap())
+        }
+
+        #[test]
+        fn from_json() {
+            let events = events("stack_events.json");
+
+            assert_eq!(events.len(), 5);
+            assert_eq!(events[0].event_id, "5");
+            assert_eq!(events[2].logical_id, "Bucket");
+            assert_eq!(events[2].status, "CREATE_FAILED");
+            assert_eq!(events[3].reason, None);
+
+            let nested = events.iter().filter(|e| e.is_nested_stack()).count();
+            assert_eq!(nested, 2);
+        }
+
+        #[test]
+        fn render() {
+            let events = events("stack_events.json");
+
+            assert_eq!(
+                events[2].render(false),
+                "2023-11-14T22:14:40.000000+00:00  Bucket  AWS::S3::Bucket  CREATE_FAILED  Resource creation cancelled"
+            );
+            assert_eq!(
+                events[3].render(true),
+                "2023-11-14T22:13:30.000000+00:00  Network  AWS::CloudFormation::Stack  \x1b[33mCREATE_IN_PROGRESS\x1b[0m"
+            );
+        }
+
+        #[test]
+        fn root_causes_of_nested_stacks() {
+            let mut all = events("stack_events.json");
+            all.extend(events("nested_stack_events.json"));
+
+            let causes = root_causes(&all);
+
+            assert_eq!(causes.len(), 1);
+            assert_eq!(causes[0].logical_id, "Vpc");
+        }
+
+        #[test]
+        fn root_causes_without_resource_failures() {
+            let all = events("stack_events.json")
+                .into_iter()
+                .filter(|e| e.logical_id != "Bucket")
+                .collect::<Vec<_>>();
+
+            let causes = root_causes(&all);
+
+            assert_eq!(causes.len(), 1);
+            assert_eq!(causes[0].logical_id, "Network");
+        }
+
+        #[test]
+        fn pages_until_an_old_event() {
+            let page = |ids: &[&str], token: Option<&str>| {
+                let events = ids
+                    .iter()
+                    .map(|id| serde_json::json!({ "EventId": id }))
+                    .collect::<Vec<_>>();
+                serde_json::json!({ "StackEvents": events, "NextToken": token })
+            };
+            let mut tokens = Vec::new();
+
+            let events = StackEvent::new_events(
+                |token| {
+                    tokens.push(token.map(ToOwned::to_owned));
+                    match token {
+                        None => Ok::<_, ()>(page(&["6", "5"], Some("a"))),
+                        Some("a") => Ok(page(&["4", "3"], Some("b"))),
+                        _ => Ok(page(&["2", "1"], None)),
+                    }
+                },
+                |e| e.event_id.as_str() > "3",
+            )
+            .unwrap();
+
+            let ids = events
+                .iter()
+                .map(|e| e.event_id.as_str())
+                .collect::<Vec<_>>();
+            assert_eq!(ids, vec!["6", "5", "4"]);
+            assert_eq!(tokens, vec![None, Some("a".to_owned())]);
+
+            let all = StackEvent::new_events(
+                |token| match token {
+                    None => Ok::<_, ()>(page(&["2"], Some("a"))),
+                    _ => Ok(page(&["1"], None)),
+                },
+                |_| true,
+            )
+            .unwrap();
+            assert_eq!(all.len(), 2);
+        }
+
+        #[test]
+        fn operation_status() {
+            assert_eq!(operation_succeeded("CREATE_IN_PROGRESS"), None);
+            assert_eq!(
+                operation_succeeded("UPDATE_COMPLETE_CLEANUP_IN_PROGRESS"),
+                None
+            );
+            assert_eq!(operation_succeeded("CREATE_COMPLETE"), Some(true));
+            assert_eq!(operation_succeeded("DELETE_COMPLETE"), Some(true));
+            assert_eq!(operation_succeeded("ROLLBACK_COMPLETE"), Some(false));
+            assert_eq!(operation_succeeded("UPDATE_ROLLBACK_COMPLETE"), Some(false));
+            assert_eq!(operation_succeeded("DELETE_FAILED"), Some(false));
+        }
+    }
+
     mod parameters_to_string {
-        use awsx::stack::util::parameters_to_string;
+        use awsx::stack::{
+            util::{parameters_to_string, Parameter},
+            Error,
+        };
         use toml::Value;
 
+        fn parameter(key: &str, parameter_type: &str, value: Value) -> Parameter {
+            Parameter {
+                key: key.to_string(),
+                parameter_type: parameter_type.to_string(),
+                value,
+                sensitive: false,
+            }
+        }
+
         #[test]
         fn test() {
             let parameters = vec![
-                ("test_str".to_string(), Value::String("abc".to_string())),
-                ("test_int".to_string(), Value::Integer(123)),
-                ("test_float".to_string(), Value::Float(123.0)),
-                ("test_bool".to_string(), Value::Boolean(true)),
+                parameter("test_str", "String", Value::String("abc".to_string())),
+                parameter("test_int", "Number", Value::Integer(123)),
+                parameter("test_float", "Number", Value::Float(123.0)),
+                parameter("test_bool", "String", Value::Boolean(true)),
+                parameter(
+                    "test_list",
+                    "List<AWS::EC2::Subnet::Id>",
+                    Value::Array(vec![
+                        Value::String("subnet-1".to_string()),
+                        Value::String("subnet-2".to_string()),
+                    ]),
+                ),
+                parameter(
+                    "test_spaces",
+                    "String",
+                    Value::String("it's a test".to_string()),
+                ),
             ];
-            let actual = parameters_to_string(parameters);
+            let actual = parameters_to_string(&parameters).unwrap();
 
             let mut expected = "--parameters".to_string();
-            expected.push_str(" ParameterKey=test_str,ParameterValue=\"abc\"");
+            expected.push_str(" ParameterKey=test_str,ParameterValue=abc");
             expected.push_str(" ParameterKey=test_int,ParameterValue=123");
             expected.push_str(" ParameterKey=test_float,ParameterValue=123.0");
             expected.push_str(" ParameterKey=test_bool,ParameterValue=true");
+            expected.push_str(" 'ParameterKey=test_list,ParameterValue=subnet-1\\,subnet-2'");
+            expected.push_str(" 'ParameterKey=test_spaces,ParameterValue=it'\\''s a test'");
 
             assert_eq!(actual, expected)
         }
+
+        #[test]
+        fn without_parameters() {
+            assert_eq!(parameters_to_string(&[]).unwrap(), "");
+        }
+
+        #[test]
+        fn rejects_unsupported_values() {
+            let mut table = toml::map::Map::new();
+            table.insert("a".to_string(), Value::Integer(1));
+            let datetime = "1979-05-27T07:32:00Z".parse().unwrap();
+
+            for invalid in [
+                parameter("test_table", "String", Value::Table(table)),
+                parameter("test_datetime", "String", Value::Datetime(datetime)),
+                parameter(
+                    "test_array",
+                    "String",
+                    Value::Array(vec![Value::String("a".to_string())]),
+                ),
+                parameter(
+                    "test_nested",
+                    "CommaDelimitedList",
+                    Value::Array(vec![Value::Array(vec![])]),
+                ),
+            ] {
+                let r = parameters_to_string(std::slice::from_ref(&invalid));
+
+                assert!(
+                    matches!(&r, Err(Error::InvalidParameter { key, .. }) if key == &invalid.key),
+                    "{} should be rejected, got {:?}",
+                    invalid.key,
+                    r
+                );
+            }
+        }
+    }
+
+    mod get_parameters_from_config {
+        use crate::tools::fixture_path;
+        use awsx::{
+            config::Config,
+            stack::{util::get_parameters_from_config, Error},
+        };
+
+        fn config() -> Config {
+            let path = fixture_path("parameters/config.toml");
+            Config::from_path(path, Default::default()).unwrap()
+        }
+
+        #[test]
+        fn skips_defaults_and_marks_no_echo() {
+            let template = fixture_path("parameters/template.yml");
+
+            let parameters = get_parameters_from_config(template, &config()).unwrap();
+
+            let keys = parameters
+                .iter()
+                .map(|p| p.key.as_str())
+                .collect::<Vec<_>>();
+            assert_eq!(
+                keys,
+                vec![
+                    "Environment",
+                    "DatabasePassword",
+                    "BucketName",
+                    "Replicas",
+                    "Subnets"
+                ]
+            );
+
+            let sensitive = parameters
+                .iter()
+                .filter(|p| p.sensitive)
+                .map(|p| p.key.as_str())
+                .collect::<Vec<_>>();
+            assert_eq!(sensitive, vec!["DatabasePassword"]);
+        }
+
+        #[test]
+        fn validates_values() {
+            let template = fixture_path("parameters/template.yml");
+            let invalid = [
+                ("Environment", "development"),
+                ("DatabasePassword", "short"),
+                ("BucketName", "Not_Valid"),
+                ("Replicas", "7"),
+                ("Replicas", "many"),
+                ("Subnets", "a,d"),
+            ];
+
+            for (key, value) in invalid {
+                let mut config = config();
+                config.set_string(format!("parameters.{}", key), value);
+
+                let r = get_parameters_from_config(&template, &config);
+
+                assert!(
+                    matches!(&r, Err(Error::InvalidParameter { key: k, .. }) if k == key),
+                    "{} = {:?} should be invalid, got {:?}",
+                    key,
+                    value,
+                    r
+                );
+            }
+        }
+
+        #[test]
+        fn no_echo_values_are_not_shown_in_errors() {
+            let template = fixture_path("parameters/template.yml");
+            let mut config = config();
+            config.set_string("parameters.DatabasePassword", "hunter2");
+
+            let r = get_parameters_from_config(&template, &config);
+
+            let message = r.unwrap_err().to_string();
+            assert!(message.contains("shorter than 8"));
+            assert!(!message.contains("hunter2"));
+        }
+
+        #[test]
+        fn tables_without_value_are_invalid() {
+            let template = fixture_path("parameters/template.yml");
+            let mut config = config();
+            config.set_bool("parameters.Environment.expose", true);
+
+            let r = get_parameters_from_config(&template, &config);
+
+            assert!(matches!(&r, Err(Error::InvalidParameter { key, .. }) if key == "Environment"));
+        }
+
+        #[test]
+        fn expressions_are_cached_per_env() {
+            let template = fixture_path("parameters/template.yml");
+            let mut config = config();
+            let expression = r#"{{ echo "$HOME-password" }}"#;
+            config.set_string("env.AWS_PROFILE", "default");
+            config.set_string("env.AWS_DEFAULT_REGION", "eu-central-1");
+            config.set_string("env.HOME", "/config-home");
+            config.set_string("env.SEEN", expression);
+            config.set_string("parameters.DatabasePassword", expression);
+
+            // env expressions only see the env table, parameters the process env on top of it
+            assert_eq!(
+                config.get_envs().get("SEEN").unwrap(),
+                "/config-home-password"
+            );
+            let parameters = get_parameters_from_config(&template, &config).unwrap();
+            let password = parameters
+                .iter()
+                .find(|p| p.key == "DatabasePassword")
+                .unwrap();
+            assert_eq!(
+                password.value.as_str().unwrap(),
+                format!("{}-password", std::env::var("HOME").unwrap())
+            );
+        }
+
+        #[test]
+        fn missing_parameter_without_default() {
+            let template = fixture_path("config_1/template.yml");
+
+            let r = get_parameters_from_config(template, &config());
+
+            assert!(matches!(r, Err(Error::MissingParameter { .. })));
+        }
     }
 
     mod get_parameter_values_from_config {
@@ -74,6 +448,66 @@ mod util {
     }
 }
 
+mod template {
+    use crate::tools::fixture_path;
+    use awsx::stack::{Error, Template};
+
+    #[test]
+    fn yaml_and_json_are_the_same() {
+        let yaml = Template::from_path(fixture_path("templates/short_forms.yml")).unwrap();
+        let json = Template::from_path(fixture_path("templates/short_forms.json")).unwrap();
+
+        assert_eq!(yaml.body(), json.body());
+    }
+
+    #[test]
+    fn short_forms() {
+        let template = Template::from_path(fixture_path("templates/short_forms.yml")).unwrap();
+        let body = template.body();
+
+        assert_eq!(
+            body.pointer("/Conditions/IsProduction/Fn::Equals/0/Ref"),
+            Some(&serde_json::json!("Environment"))
+        );
+        assert_eq!(
+            body.pointer("/Outputs/BucketArn/Value/Fn::GetAtt"),
+            Some(&serde_json::json!(["Bucket", "Arn"]))
+        );
+        assert!(template.section("Resources").unwrap().is_some());
+        assert!(template.section("Mappings").unwrap().is_none());
+    }
+
+    #[test]
+    fn parameters() {
+        let template = Template::from_path(fixture_path("templates/short_forms.json")).unwrap();
+        let parameters = template.parameters().unwrap();
+
+        assert_eq!(parameters.len(), 2);
+        assert_eq!(parameters[0].name, "Environment");
+        assert_eq!(parameters[0].allowed_values, vec!["staging", "production"]);
+        assert_eq!(parameters[1].parameter_type, "Number");
+        assert_eq!(parameters[1].default.as_deref(), Some("2"));
+    }
+
+    #[test]
+    fn rejects_invalid_files() {
+        for fixture in [
+            "templates/unknown_tag.yml",
+            "templates/multiple_documents.yml",
+            "templates/not_a_mapping.json",
+        ] {
+            let r = Template::from_path(fixture_path(fixture));
+
+            assert!(
+                matches!(r, Err(Error::InvalidTemplate { .. })),
+                "{} should be invalid, got {:?}",
+                fixture,
+                r
+            );
+        }
+    }
+}
+
 mod cli {
     mod validate {
         use crate::tools::fixture_path;
@@ -1,7 +1,52 @@
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 // pub type Result<T = ()> = std::result::Result<T, Box<dyn std::error::Error>>;
 
 pub fn fixture_path(fixture_name: &str) -> PathBuf {
     PathBuf::from_iter(["tests", "fixtures", fixture_name])
 }
+
+/// Copies a fixture directory into a fresh temporary directory, so tests can modify its files
+pub fn temp_fixture(fixture_name: &str, test_name: &str) -> PathBuf {
+    let target = std::env::temp_dir().join("awsx-tests").join(format!(
+        "{}-{}",
+        test_name,
+        std::process::id()
+    ));
+
+    if target.exists() {
+        std::fs::remove_dir_all(&target).unwrap();
+    }
+    copy_dir(&fixture_path(fixture_name), &target);
+
+    target
+}
+
+fn copy_dir(from: &PathBuf, to: &PathBuf) {
+    std::fs::create_dir_all(to).unwrap();
+    for entry in std::fs::read_dir(from).unwrap() {
+        let entry = entry.unwrap();
+        if entry.file_type().unwrap().is_dir() {
+            copy_dir(&entry.path(), &to.join(entry.file_name()));
+        } else {
+            std::fs::copy(entry.path(), to.join(entry.file_name())).unwrap();
+        }
+    }
+}
+
+/// Writes an executable `aws` script with the given body to `<dir>/bin` and returns a PATH
+/// that finds it before the real one
+pub fn fake_aws(dir: &Path, body: &str) -> String {
+    let bin = dir.join("bin");
+    std::fs::create_dir_all(&bin).unwrap();
+
+    let script = bin.join("aws");
+    std::fs::write(&script, format!("#!/bin/sh\n{}", body)).unwrap();
+    std::process::Command::new("chmod")
+        .arg("+x")
+        .arg(&script)
+        .status()
+        .unwrap();
+
+    format!("{}:{}", bin.display(), std::env::var("PATH").unwrap())
+}